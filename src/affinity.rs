@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::process::Command;
+use std::str::FromStr;
+
+/// A set of CPUs written as a list of indexes and ranges, e.g. `0,2-7`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CpuList(pub Vec<usize>);
+
+impl fmt::Display for CpuList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.iter().map(usize::to_string).collect::<Vec<_>>().join(","))
+    }
+}
+
+impl From<CpuList> for String {
+    fn from(cpus: CpuList) -> Self {
+        cpus.to_string()
+    }
+}
+
+impl TryFrom<String> for CpuList {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// Number of CPUs a cpu_set_t can hold.
+const MAX_CPUS: usize = 1024;
+
+// Number of CPUs online on this machine, if it is known.
+#[cfg(unix)]
+fn online_cpus() -> Option<usize> {
+    let count = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    usize::try_from(count).ok().filter(|count| *count > 0)
+}
+
+#[cfg(not(unix))]
+fn online_cpus() -> Option<usize> {
+    None
+}
+
+impl FromStr for CpuList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CpuList::parse(s, online_cpus().unwrap_or(MAX_CPUS).min(MAX_CPUS))
+    }
+}
+
+impl CpuList {
+    // Parses a list of CPUs numbered below `limit`.
+    fn parse(s: &str, limit: usize) -> Result<Self, String> {
+        let mut cpus = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let parse = |v: &str| {
+                let cpu = v
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid CPU index `{}`", v))?;
+                if cpu >= limit {
+                    return Err(format!(
+                        "CPU {} does not exist, the online CPUs are numbered from 0 to {}",
+                        cpu,
+                        limit - 1
+                    ));
+                }
+                Ok(cpu)
+            };
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse(start)?, parse(end)?);
+                    if start > end {
+                        return Err(format!("invalid CPU range `{}`", part));
+                    }
+                    cpus.extend(start..=end);
+                }
+                None => cpus.push(parse(part)?),
+            }
+        }
+        if cpus.is_empty() {
+            return Err("the CPU list is empty".to_string());
+        }
+        cpus.sort_unstable();
+        cpus.dedup();
+        Ok(CpuList(cpus))
+    }
+}
+
+/// Scheduling settings applied to the measured command before it starts.
+#[derive(Clone, Debug, Default)]
+pub struct ChildScheduling {
+    pub cpus: Option<CpuList>,
+    pub nice: Option<i32>,
+}
+
+impl ChildScheduling {
+    pub fn is_empty(&self) -> bool {
+        self.cpus.is_none() && self.nice.is_none()
+    }
+
+    /// Configures the command so that the scheduling settings are applied in the child
+    /// process, between the fork and the exec of the command.
+    #[cfg(target_os = "linux")]
+    pub fn apply(&self, cmd: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        if self.is_empty() {
+            return;
+        }
+        let settings = self.clone();
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(cpus) = &settings.cpus {
+                    set_affinity(cpus)?;
+                }
+                if let Some(nice) = settings.nice {
+                    set_nice(nice)?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply(&self, _cmd: &mut Command) {
+        if !self.is_empty() {
+            eprintln!("[WARNING] CPU affinity and nice level are only supported on Linux.");
+        }
+    }
+}
+
+/// Pins the calling thread to `cpu` and optionally gives it a real-time priority.
+#[cfg(target_os = "linux")]
+pub fn configure_sampler(cpu: Option<usize>, rt_priority: Option<i32>) -> io::Result<()> {
+    if let Some(cpu) = cpu {
+        set_affinity(&CpuList(vec![cpu]))?;
+    }
+    if let Some(priority) = rt_priority {
+        let param = libc::sched_param {
+            sched_priority: priority,
+        };
+        if unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn configure_sampler(cpu: Option<usize>, rt_priority: Option<i32>) -> io::Result<()> {
+    if cpu.is_some() || rt_priority.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sampler affinity and priority are only supported on Linux",
+        ));
+    }
+    Ok(())
+}
+
+// Only async-signal-safe calls are allowed here since it also runs after the fork.
+#[cfg(target_os = "linux")]
+fn set_affinity(cpus: &CpuList) -> io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for cpu in &cpus.0 {
+            // CPU_SET panics out of the set, the error does not allocate
+            if *cpu >= libc::CPU_SETSIZE as usize {
+                return Err(io::Error::from_raw_os_error(libc::EINVAL));
+            }
+            libc::CPU_SET(*cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_nice(nice: i32) -> io::Result<()> {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cpu_list() {
+        assert_eq!(CpuList::parse("0,2-3, 1", 8), Ok(CpuList(vec![0, 1, 2, 3])));
+        assert!(CpuList::parse("3-1", 8).is_err());
+        assert!(CpuList::parse("", 8).is_err());
+    }
+
+    #[test]
+    fn rejects_cpus_out_of_range() {
+        assert!(CpuList::parse("7", 8).is_ok());
+        assert!(CpuList::parse("8", 8).unwrap_err().contains("CPU 8 does not exist"));
+        assert!(CpuList::parse("0-100000000000", 8).is_err());
+        assert!("5000".parse::<CpuList>().is_err());
+    }
+}
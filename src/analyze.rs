@@ -0,0 +1,413 @@
+use clap::Args;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::energy::{is_energy, primary_domain, EnergyAccumulator};
+use crate::phase::{PhaseEvent, PhaseTracker};
+use crate::plot::{Chart, PlotError};
+use crate::summary::{print_phases, Summary};
+use crate::wraparound::set_counter_range;
+
+// Number of values of the 32 bits energy counters.
+const COUNTER_VALUES: f64 = 4_294_967_296.0;
+// The energy unit of MSR_RAPL_POWER_UNIT is 1/2^ESU joules with a 5 bits ESU.
+const MAX_ENERGY_UNIT_BITS: i32 = 31;
+const SERVER_DRAM_UNIT: f64 = 15.3e-6;
+// Distinct values of a counter needed to infer its unit, fewer values can all be
+// multiples of a coarser unit by chance.
+const MIN_INFERENCE_VALUES: usize = 16;
+
+// Separators tried when the header does not tell which one is used.
+const SEPARATORS: [&str; 5] = [",", ";", "\t", "|", " "];
+
+/// Options of the `analyze` subcommand.
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    /// CSV output of EnergiBridge, may be compressed with gzip (.gz) or zstd (.zst)
+    pub file: PathBuf,
+
+    /// Separator of the columns, detected from the header by default
+    #[arg(short, long)]
+    pub separator: Option<String>,
+
+    /// Ignore the samples taken before this number of seconds since the first one
+    #[arg(long)]
+    pub from: Option<f64>,
+
+    /// Ignore the samples taken after this number of seconds since the first one
+    #[arg(long)]
+    pub to: Option<f64>,
+
+    /// Draw the power, usage and temperature over time to this SVG or PNG file
+    #[arg(long)]
+    pub plot: Option<PathBuf>,
+
+    /// Metadata of the run saved with --metadata, gives the range of the energy counters
+    #[arg(long)]
+    pub metadata: Option<PathBuf>,
+}
+
+#[derive(Error, Debug)]
+pub enum AnalyzeError {
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, io::Error),
+    #[error("{0} is not an output of EnergiBridge, the header must start with Delta and Time")]
+    Header(PathBuf),
+    #[error("invalid value `{1}` on line {0}")]
+    Value(usize, String),
+    #[error("no sample in the selected time window")]
+    Empty,
+    #[error("invalid metadata {0}: {1}")]
+    Metadata(PathBuf, serde_json::Error),
+    #[error(transparent)]
+    Plot(#[from] PlotError),
+}
+
+/// A sample read back from an output file.
+pub struct Row {
+    /// Unix time in milliseconds.
+    pub time: u64,
+    pub results: HashMap<String, f64>,
+    pub phase: Option<String>,
+}
+
+/// The samples of a recorded run.
+pub struct Recording {
+    pub keys: Vec<String>,
+    pub rows: Vec<Row>,
+}
+
+impl Recording {
+    pub fn read(path: &Path, separator: Option<&str>) -> Result<Recording, AnalyzeError> {
+        let io_error = |e| AnalyzeError::Io(path.to_path_buf(), e);
+        let mut lines = open_input(path).map_err(io_error)?.lines();
+        let header = match lines.next() {
+            Some(header) => header.map_err(io_error)?,
+            None => return Err(AnalyzeError::Header(path.to_path_buf())),
+        };
+        let sep = match separator {
+            Some(sep) => sep,
+            None => SEPARATORS
+                .into_iter()
+                .find(|sep| header.starts_with(&format!("Delta{}Time", sep)))
+                .ok_or_else(|| AnalyzeError::Header(path.to_path_buf()))?,
+        };
+        let mut columns: Vec<String> = header.trim_end().split(sep).map(String::from).collect();
+        if columns.len() < 2 || columns[0] != "Delta" || columns[1] != "Time" {
+            return Err(AnalyzeError::Header(path.to_path_buf()));
+        }
+        let has_phase = columns.last().is_some_and(|c| c == "PHASE");
+        if has_phase {
+            columns.pop();
+        }
+        let keys = columns.split_off(2);
+
+        let mut rows = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let line = line.map_err(io_error)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // the header is the first line
+            let number = index + 2;
+            let mut fields = line.trim_end_matches(['\r', '\n']).split(sep);
+            let parse = |field: Option<&str>| {
+                let field = field.unwrap_or_default();
+                field
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| AnalyzeError::Value(number, field.to_string()))
+            };
+            parse(fields.next())?;
+            let time = parse(fields.next())? as u64;
+            let mut results = HashMap::new();
+            for key in &keys {
+                // an empty field is a value that could not be read
+                let field = fields.next();
+                if field.is_some_and(|field| field.trim().is_empty()) {
+                    continue;
+                }
+                results.insert(key.clone(), parse(field)?);
+            }
+            let phase = has_phase.then(|| fields.next().unwrap_or_default().to_string());
+            rows.push(Row {
+                time,
+                results,
+                phase,
+            });
+        }
+        Ok(Recording { keys, rows })
+    }
+
+    /// The samples taken between `from` and `to` seconds after the first one.
+    pub fn window(&self, from: Option<f64>, to: Option<f64>) -> &[Row] {
+        let Some(first) = self.rows.first() else {
+            return &[];
+        };
+        let offset = |row: &Row| row.time.saturating_sub(first.time) as f64 / 1000.0;
+        let start = self
+            .rows
+            .iter()
+            .position(|row| offset(row) >= from.unwrap_or(0.0))
+            .unwrap_or(self.rows.len());
+        let end = self
+            .rows
+            .iter()
+            .rposition(|row| offset(row) <= to.unwrap_or(f64::INFINITY))
+            .map_or(0, |end| end + 1);
+        &self.rows[start..end.max(start)]
+    }
+
+    /// Registers the range of the energy counters so that their wraps are detected.
+    ///
+    /// The ranges known from the metadata of the run are used when they are given, the
+    /// others are inferred from the values of the counter.
+    pub fn set_counter_ranges(&self, known: &HashMap<String, f64>) {
+        for key in self.keys.iter().filter(|key| is_energy(key)) {
+            let range = match known.get(key) {
+                Some(range) => Some(*range),
+                None => self.infer_counter_range(key),
+            };
+            match range {
+                Some(range) => set_counter_range(key, range),
+                None if self.wraps(key) => eprintln!(
+                    "[WARNING] The range of {} is unknown, its counter is assumed to restart from zero when it wraps around. Give the metadata of the run with --metadata.",
+                    key
+                ),
+                None => {}
+            }
+        }
+    }
+
+    // Whether the counter decreases between two samples.
+    fn wraps(&self, key: &str) -> bool {
+        self.rows.windows(2).any(|pair| {
+            match (pair[0].results.get(key), pair[1].results.get(key)) {
+                (Some(previous), Some(current)) => current < previous,
+                _ => false,
+            }
+        })
+    }
+
+    // The RAPL counters are 32 bits counts of an energy unit, which is a power of two
+    // except for the DRAM of the server parts. The unit is the finest granularity of the
+    // recorded values, provided there are enough of them.
+    fn infer_counter_range(&self, key: &str) -> Option<f64> {
+        let mut values: Vec<f64> = self
+            .rows
+            .iter()
+            .filter_map(|row| row.results.get(key).copied())
+            .filter(|value| *value > 0.0)
+            .collect();
+        values.sort_by(f64::total_cmp);
+        values.dedup();
+        if values.len() < MIN_INFERENCE_VALUES {
+            return None;
+        }
+        let multiple_of = |unit: f64| {
+            values.iter().all(|value| {
+                let count = value / unit;
+                (count - count.round()).abs() <= count * 1e-9
+            })
+        };
+        let unit = (0..=MAX_ENERGY_UNIT_BITS)
+            .map(|bits| 0.5f64.powi(bits))
+            .find(|unit| values.iter().all(|value| (value / unit).fract() == 0.0))
+            .or_else(|| multiple_of(SERVER_DRAM_UNIT).then_some(SERVER_DRAM_UNIT))?;
+        Some(unit * COUNTER_VALUES)
+    }
+}
+
+/// Prints the summary of a recorded run, as `--summary` does at the end of a run.
+pub fn run(args: &AnalyzeArgs) -> Result<(), AnalyzeError> {
+    let recording = Recording::read(&args.file, args.separator.as_deref())?;
+    let counter_ranges = match &args.metadata {
+        Some(path) => read_metadata(path)?.counter_ranges(),
+        None => HashMap::new(),
+    };
+    recording.set_counter_ranges(&counter_ranges);
+    let rows = recording.window(args.from, args.to);
+    let Some(first) = rows.first() else {
+        return Err(AnalyzeError::Empty);
+    };
+
+    let mut accumulator = EnergyAccumulator::new();
+    accumulator.update(&first.results, Duration::ZERO);
+    let mut summary = Summary::new();
+    let mut phases = PhaseTracker::new();
+    // the rows are placed on a monotonic clock to split the intervals between the phases
+    let origin = Instant::now();
+    let at = |time: u64| origin + Duration::from_millis(time.saturating_sub(first.time));
+    send_phase_events(&phases, origin, "", first.phase.as_deref().unwrap_or_default());
+    phases.update(origin, Duration::ZERO, None);
+    let mut chart = Chart::new();
+    chart.add(first.time, &first.results, accumulator.totals(), first.phase.as_deref());
+    for pair in rows.windows(2) {
+        let (previous, row) = (&pair[0], &pair[1]);
+        let elapsed = Duration::from_millis(row.time.saturating_sub(previous.time));
+        let deltas = accumulator.update(&row.results, elapsed);
+        summary.update(&deltas, elapsed);
+
+        // the phases changed at an unknown time between the two samples, the interval
+        // is split in the middle between the phases of the first one and of the second one
+        send_phase_events(
+            &phases,
+            at(previous.time) + elapsed / 2,
+            previous.phase.as_deref().unwrap_or_default(),
+            row.phase.as_deref().unwrap_or_default(),
+        );
+        let energy = primary_domain(&row.results)
+            .and_then(|key| deltas.get(key))
+            .copied();
+        phases.update(at(row.time), elapsed, energy);
+        chart.add(row.time, &row.results, accumulator.totals(), row.phase.as_deref());
+    }
+
+    println!("{} samples from {}", rows.len(), args.file.display());
+    summary.print(primary_domain(&first.results), summary.duration(), true);
+    print_phases(phases.stats());
+    if let Some(path) = &args.plot {
+        chart.save(path)?;
+    }
+    Ok(())
+}
+
+// Reads the ranges of the energy counters saved in the metadata of a run.
+// What the metadata saved with --metadata tells about the energy counters of a run.
+#[derive(serde::Deserialize)]
+struct RunMetadata {
+    #[serde(default)]
+    counter_ranges: HashMap<String, f64>,
+    #[cfg(not(target_os = "macos"))]
+    #[serde(default)]
+    cpu: Option<crate::cpu::model::CpuId>,
+}
+
+impl RunMetadata {
+    // The saved ranges, and the range of the DRAM counter of the microarchitectures
+    // counting it in a fixed unit for the runs that did not save them.
+    fn counter_ranges(self) -> HashMap<String, f64> {
+        let dram = self.dram_range();
+        let mut ranges = self.counter_ranges;
+        if let Some(range) = dram {
+            ranges.entry("DRAM_ENERGY (J)".to_string()).or_insert(range);
+        }
+        ranges
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn dram_range(&self) -> Option<f64> {
+        let arch = crate::cpu::model::microarchitecture(self.cpu.as_ref()?)?;
+        Some(arch.dram_energy_unit? * COUNTER_VALUES)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn dram_range(&self) -> Option<f64> {
+        None
+    }
+}
+
+fn read_metadata(path: &Path) -> Result<RunMetadata, AnalyzeError> {
+    let file = File::open(path).map_err(|e| AnalyzeError::Io(path.to_path_buf(), e))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| AnalyzeError::Metadata(path.to_path_buf(), e))
+}
+
+// Sends the events changing the active phases from one label to another.
+fn send_phase_events(phases: &PhaseTracker, at: Instant, previous: &str, current: &str) {
+    let split = |label: &str| -> Vec<String> {
+        label
+            .split('+')
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect()
+    };
+    let (previous, current) = (split(previous), split(current));
+    let sender = phases.sender();
+    for name in previous.iter().filter(|name| !current.contains(name)) {
+        let _ = sender.send_at(at, PhaseEvent::End(name.clone()));
+    }
+    for name in current.iter().filter(|name| !previous.contains(name)) {
+        let _ = sender.send_at(at, PhaseEvent::Begin(name.clone()));
+    }
+}
+
+// Opens a file, decompressing it according to its extension.
+fn open_input(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    Ok(match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))),
+        Some("zst") => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+        _ => Box::new(BufReader::new(file)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(key: &str, values: &[f64]) -> Recording {
+        let rows = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Row {
+                time: i as u64 * 200,
+                results: HashMap::from([(key.to_string(), *value)]),
+                phase: None,
+            })
+            .collect();
+        Recording {
+            keys: vec![key.to_string()],
+            rows,
+        }
+    }
+
+    // Values of a counter increasing by irregular steps of its unit.
+    fn counts(start: u64, unit: f64) -> Vec<f64> {
+        (0..MIN_INFERENCE_VALUES as u64)
+            .map(|i| (start + i * 1_237 + i * i * 31) as f64 * unit)
+            .collect()
+    }
+
+    #[test]
+    fn counter_range_from_unit() {
+        // 2^-14 J, as in most client parts
+        let values = counts(1_000_003, 0.5f64.powi(14));
+        let key = "PACKAGE_ENERGY (J)";
+        assert_eq!(recording(key, &values).infer_counter_range(key), Some(262_144.0));
+
+        // 15.3 µJ for the DRAM of the server parts
+        let values = counts(3_000_001, 15.3e-6);
+        let key = "DRAM_ENERGY (J)";
+        let range = recording(key, &values).infer_counter_range(key).unwrap();
+        assert!((range - 65_712.999).abs() < 0.001, "{}", range);
+
+        assert_eq!(recording(key, &[0.0, 0.0]).infer_counter_range(key), None);
+    }
+
+    #[test]
+    fn short_recording_has_no_inferred_range() {
+        // the counts of 2^-14 J are all even, they look like counts of 2^-13 J
+        let unit = 0.5f64.powi(14);
+        let values: Vec<f64> = [1_000_002u64, 1_002_220, 1_004_528].map(|n| n as f64 * unit).into();
+        let key = "PACKAGE_ENERGY (J)";
+        assert_eq!(recording(key, &values).infer_counter_range(key), None);
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn dram_range_from_the_microarchitecture() {
+        let metadata: RunMetadata = serde_json::from_str(
+            r#"{"counter_ranges": {"PACKAGE_ENERGY (J)": 262144.0},
+                "cpu": {"vendor": "GenuineIntel", "family": 6, "model": 85}}"#,
+        )
+        .unwrap();
+        let ranges = metadata.counter_ranges();
+        assert_eq!(ranges["PACKAGE_ENERGY (J)"], 262_144.0);
+        assert!((ranges["DRAM_ENERGY (J)"] - 65_712.999).abs() < 0.001);
+    }
+}
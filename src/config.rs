@@ -0,0 +1,75 @@
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Configuration file loaded from the working directory when `--config` is not used.
+pub const DEFAULT_CONFIG_FILE: &str = "energibridge.toml";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("failed to parse {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+    #[error("unknown option `{0}` in the configuration file")]
+    UnknownOption(String),
+    #[error("profile `{0}` is not defined in the configuration file")]
+    UnknownProfile(String),
+    #[error("invalid value in the configuration file: {0}")]
+    Invalid(serde_json::Error),
+}
+
+/// Returns the configuration file to use, if any.
+pub fn config_path(path: Option<&str>) -> Option<PathBuf> {
+    match path {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|p| p.exists()),
+    }
+}
+
+/// Applies the options of the configuration file to `args`.
+///
+/// The top-level keys of the file are the long names of the command line options
+/// (with `-` or `_`), the `[profile.<name>]` tables define named profiles that
+/// override them. Options given on the command line always take precedence.
+pub fn apply_config<T: Serialize + DeserializeOwned>(
+    args: T,
+    matches: &ArgMatches,
+    path: &Path,
+    profile: Option<&str>,
+) -> Result<T, ConfigError> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+    let mut config: toml::Table =
+        toml::from_str(&content).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+
+    let profiles = config.remove("profile");
+    if let Some(name) = profile {
+        let options = profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+            .and_then(|profile| profile.as_table())
+            .ok_or_else(|| ConfigError::UnknownProfile(name.to_string()))?;
+        config.extend(options.clone());
+    }
+
+    let mut values = match serde_json::to_value(args).map_err(ConfigError::Invalid)? {
+        Value::Object(values) => values,
+        _ => unreachable!("the arguments are a struct"),
+    };
+    for (key, value) in config {
+        let id = key.replace('-', "_");
+        if !values.contains_key(&id) {
+            return Err(ConfigError::UnknownOption(key));
+        }
+        if matches.value_source(&id) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        values.insert(id, serde_json::to_value(value).map_err(ConfigError::Invalid)?);
+    }
+    serde_json::from_value(Value::Object(values)).map_err(ConfigError::Invalid)
+}
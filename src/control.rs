@@ -0,0 +1,213 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::sync::mpsc::channel;
+use std::sync::mpsc::{Receiver, Sender};
+#[cfg(unix)]
+use std::thread;
+use std::time::Instant;
+
+use crate::energy::EnergyAccumulator;
+use crate::phase::{PhaseEvent, PhaseTracker};
+
+/// Commands accepted on the control socket, one per line.
+#[derive(Debug, PartialEq)]
+pub enum ControlCommand {
+    StartWindow,
+    StopWindow,
+    Mark(String),
+    Snapshot,
+    Stop,
+}
+
+impl ControlCommand {
+    pub fn parse(line: &str) -> Option<ControlCommand> {
+        let line = line.trim();
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+        match (name, arg) {
+            ("start-window", "") => Some(ControlCommand::StartWindow),
+            ("stop-window", "") => Some(ControlCommand::StopWindow),
+            ("mark", label) if !label.is_empty() => Some(ControlCommand::Mark(label.to_string())),
+            ("snapshot", "") => Some(ControlCommand::Snapshot),
+            ("stop", "") => Some(ControlCommand::Stop),
+            _ => None,
+        }
+    }
+}
+
+/// A command received from a client, waiting for the sampling loop to answer it.
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Sender<Value>,
+}
+
+impl ControlRequest {
+    pub fn reply(self, response: Value) {
+        // the client may have disconnected in the meantime
+        let _ = self.reply.send(response);
+    }
+}
+
+/// Unix socket used by external harnesses to drive the measurement.
+///
+/// The connections are served by background threads, the commands are forwarded to
+/// the sampling loop which answers them at its next sample with a JSON line.
+pub struct ControlSocket {
+    path: PathBuf,
+    receiver: Receiver<ControlRequest>,
+}
+
+impl ControlSocket {
+    #[cfg(not(unix))]
+    pub fn bind(_path: &Path) -> io::Result<ControlSocket> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the control socket is only supported on Unix systems",
+        ))
+    }
+
+    #[cfg(unix)]
+    pub fn bind(path: &Path) -> io::Result<ControlSocket> {
+        let listener = UnixListener::bind(path)?;
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                thread::spawn(move || serve(stream, sender));
+            }
+        });
+        Ok(ControlSocket {
+            path: path.to_path_buf(),
+            receiver,
+        })
+    }
+
+    /// Returns the next pending request, if any.
+    pub fn try_recv(&self) -> Option<ControlRequest> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn serve(stream: UnixStream, sender: Sender<ControlRequest>) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match ControlCommand::parse(&line) {
+            Some(command) => {
+                let (reply, response) = channel();
+                if sender.send(ControlRequest { command, reply }).is_err() {
+                    break;
+                }
+                match response.recv() {
+                    Ok(response) => response,
+                    Err(_) => break,
+                }
+            }
+            None => json!({ "error": format!("unknown command: {}", line.trim()) }),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            break;
+        }
+    }
+}
+
+/// A measurement window opened with `start-window`.
+pub struct Window {
+    start: Instant,
+    totals: HashMap<String, f64>,
+}
+
+impl Window {
+    pub fn start(totals: &HashMap<String, f64>) -> Self {
+        Window {
+            start: Instant::now(),
+            totals: totals.clone(),
+        }
+    }
+
+    /// Energy consumed by each domain since the window was opened.
+    pub fn report(&self, totals: &HashMap<String, f64>) -> Value {
+        let energy: HashMap<&String, f64> = totals
+            .iter()
+            .map(|(key, total)| (key, total - self.totals.get(key).unwrap_or(&0.0)))
+            .collect();
+        json!({
+            "duration_ms": self.start.elapsed().as_millis() as u64,
+            "energy": energy,
+        })
+    }
+}
+
+/// State of the measurement driven through the control socket.
+#[derive(Default)]
+pub struct ControlState {
+    window: Option<Window>,
+    mark: Option<String>,
+    stop: bool,
+}
+
+impl ControlState {
+    /// Answers a request received on the control socket.
+    pub fn handle(
+        &mut self,
+        request: ControlRequest,
+        accumulator: &EnergyAccumulator,
+        start_time: Instant,
+        phases: &PhaseTracker,
+    ) {
+        let response = match &request.command {
+            ControlCommand::StartWindow => {
+                self.window = Some(Window::start(accumulator.totals()));
+                json!({ "status": "ok" })
+            }
+            ControlCommand::StopWindow => match self.window.take() {
+                Some(window) => window.report(accumulator.totals()),
+                None => json!({ "error": "no window started" }),
+            },
+            ControlCommand::Mark(label) => {
+                // a mark starts a phase that lasts until the next mark
+                let sender = phases.sender();
+                if let Some(previous) = self.mark.replace(label.clone()) {
+                    let _ = sender.send(PhaseEvent::End(previous));
+                }
+                let _ = sender.send(PhaseEvent::Begin(label.clone()));
+                json!({ "status": "ok" })
+            }
+            ControlCommand::Snapshot => json!({
+                "elapsed_ms": start_time.elapsed().as_millis() as u64,
+                "energy": accumulator.totals(),
+            }),
+            ControlCommand::Stop => {
+                self.stop = true;
+                json!({ "status": "ok" })
+            }
+        };
+        request.reply(response);
+    }
+
+    /// Returns true once a client asked to stop the measurement.
+    pub fn stop_requested(&self) -> bool {
+        self.stop
+    }
+}
@@ -1,52 +1,141 @@
+use once_cell::sync::OnceCell;
 use std::collections::HashMap;
 use sysinfo::{System};
 
-use super::{get_number_cores, msr::read_msr_on_core};
+use super::get_number_cores;
+use super::msr::{probe_msr, read_msr_on_core, MSR_PRIVILEGES, RAPL_UPDATE_RATE};
+use crate::wraparound::set_counter_range;
+use crate::metrics::MetricFilter;
+use crate::sensor::{warn_read_failure, SensorInfo};
 
 pub const AMD_MSR_PWR_UNIT: u32 = 0xC0010299;
 const AMD_MSR_CORE_ENERGY: u32 = 0xC001029A;
 const AMD_MSR_PACKAGE_ENERGY: u32 = 0xC001029B;
 const AMD_MSR_FID: u32 = 0xC0010293;
+const AMD_MSR_PSTATE: u32 = 0xC0010063;
 
 const AMD_ENERGY_UNIT_MASK: u32 = 0x1F00;
+// The energy status MSRs are 32 bits counters that wrap around
+const AMD_ENERGY_STATUS_MASK: u64 = 0xFFFFFFFF;
 
-pub fn get_amd_cpu_counter(sys: &mut System, results: &mut HashMap<String, f64>) {
+// Column names of the metrics of a core.
+fn core_metric_names(core: u32) -> [String; 4] {
+    [
+        format!("CORE{}_VOLT (V)", core),
+        format!("CORE{}_FREQ (MHZ)", core),
+        format!("CORE{}_PSTATE", core),
+        format!("CORE{}_ENERGY (J)", core),
+    ]
+}
+
+/// Describes the energy counters and the core metrics supported by the CPU.
+pub fn probe_amd_sensors(sys: &mut System) -> Vec<SensorInfo> {
     #[cfg(target_os = "linux")]
     let nb_core = get_number_cores(sys).unwrap() as u32;
     #[cfg(target_os = "windows")]
     let nb_core = 1;
 
-    unsafe {
-        let core_energy_units: u64 = read_msr_on_core(AMD_MSR_PWR_UNIT, 0).unwrap();
-        let energy_unit: u64 = (core_energy_units & AMD_ENERGY_UNIT_MASK as u64) >> 8;
-        let energy_unit_d = 0.5f64.powf(energy_unit as f64);
+    let mut sensors = Vec::new();
+    let mut add = |key: &str, msr: u32, update_rate: &'static str| {
+        if let Some(readable) = probe_msr(msr) {
+            let source = format!("MSR {:#x}", msr);
+            sensors.push(SensorInfo::new(key, source, update_rate).requires(MSR_PRIVILEGES, readable));
+        }
+    };
+    add("CPU_ENERGY (J)", AMD_MSR_PACKAGE_ENERGY, RAPL_UPDATE_RATE);
+    for core in 0..nb_core {
+        let [volt_key, freq_key, pstate_key, energy_key] = core_metric_names(core);
+        add(&volt_key, AMD_MSR_FID, "every sample");
+        add(&freq_key, AMD_MSR_FID, "every sample");
+        add(&pstate_key, AMD_MSR_PSTATE, "every sample");
+        add(&energy_key, AMD_MSR_CORE_ENERGY, RAPL_UPDATE_RATE);
+    }
+    sensors
+}
 
-        for core in 0..nb_core {
-            let core_energy_raw = read_msr_on_core(AMD_MSR_CORE_ENERGY, core).unwrap();
-            let package_raw = read_msr_on_core(AMD_MSR_PACKAGE_ENERGY, core).unwrap();
-            let pstate = read_msr_on_core(0xC0010063, core).unwrap();
+pub fn get_amd_cpu_counter(
+    sys: &mut System,
+    filter: &MetricFilter,
+    results: &mut HashMap<String, f64>,
+) {
+    #[cfg(target_os = "linux")]
+    let nb_core = get_number_cores(sys).unwrap() as u32;
+    #[cfg(target_os = "windows")]
+    let nb_core = 1;
 
-            let fid = read_msr_on_core(AMD_MSR_FID, core).unwrap();
+    let mut names = vec!["CPU_ENERGY (J)".to_string()];
+    for core in 0..nb_core {
+        names.extend(core_metric_names(core));
+    }
+    if !filter.allows_any(&names) {
+        return;
+    }
 
-            let Did = ((fid >> 8) & 0x3F) as f64;
-            let Fid = (fid & 0xFF) as f64;
-            let Vid = ((fid >> 14) & 0xff) as f64;
+    // a register that cannot be read leaves the metrics computed from it missing
+    let read = |msr: u32, core: u32, keys: &[&str]| match unsafe { read_msr_on_core(msr, core) } {
+        Ok(value) => Some(value),
+        Err(e) => {
+            for key in keys.iter().filter(|key| filter.allows(key)) {
+                warn_read_failure(key, format_args!("MSR {:#x}: {}", msr, e));
+            }
+            None
+        }
+    };
 
-            let ratio = 25f64 * Fid / (12.5 * (Did));
-            let freq_mhz = ratio * 100f64;
-            let volts = 1.55 - (Vid) * 0.00625;
+    // the unit is constant, it is only read on the first sample
+    static ENERGY_UNIT: OnceCell<f64> = OnceCell::new();
+    let energy_unit_d = ENERGY_UNIT.get().copied().or_else(|| {
+        let keys: Vec<&str> = names.iter().map(String::as_str).collect();
+        let core_energy_units: u64 = read(AMD_MSR_PWR_UNIT, 0, &keys)?;
+        let energy_unit: u64 = (core_energy_units & AMD_ENERGY_UNIT_MASK as u64) >> 8;
+        Some(*ENERGY_UNIT.get_or_init(|| 0.5f64.powf(energy_unit as f64)))
+    });
+    let Some(energy_unit_d) = energy_unit_d else {
+        return;
+    };
+    let counter_range = (AMD_ENERGY_STATUS_MASK + 1) as f64 * energy_unit_d;
 
-            results.insert(format!("CORE{}_VOLT (V)", core), volts);
-            results.insert(format!("CORE{}_FREQ (MHZ)", core), freq_mhz);
-            results.insert(format!("CORE{}_PSTATE", core), (pstate & 0x07) as f64);
-            results.insert(
-                format!("CORE{}_ENERGY (J)", core),
-                core_energy_raw as f64 * energy_unit_d,
-            );
+    if filter.allows("CPU_ENERGY (J)") {
+        // the package counter is shared by all the cores of the socket
+        if let Some(package_raw) = read(AMD_MSR_PACKAGE_ENERGY, 0, &["CPU_ENERGY (J)"]) {
+            set_counter_range("CPU_ENERGY (J)", counter_range);
             results.insert(
-                format!("CPU_ENERGY (J)"),
-                package_raw as f64 * energy_unit_d,
+                "CPU_ENERGY (J)".to_string(),
+                (package_raw & AMD_ENERGY_STATUS_MASK) as f64 * energy_unit_d,
             );
         }
     }
+
+    for core in 0..nb_core {
+        let [volt_key, freq_key, pstate_key, energy_key] = core_metric_names(core);
+
+        if filter.allows_any(&[&volt_key, &freq_key]) {
+            if let Some(fid) = read(AMD_MSR_FID, core, &[&volt_key, &freq_key]) {
+                let did = ((fid >> 8) & 0x3F) as f64;
+                let fid_ratio = (fid & 0xFF) as f64;
+                let vid = ((fid >> 14) & 0xff) as f64;
+
+                let ratio = 25f64 * fid_ratio / (12.5 * did);
+                let freq_mhz = ratio * 100f64;
+                let volts = 1.55 - vid * 0.00625;
+
+                results.insert(volt_key, volts);
+                results.insert(freq_key, freq_mhz);
+            }
+        }
+        if filter.allows(&pstate_key) {
+            if let Some(pstate) = read(AMD_MSR_PSTATE, core, &[&pstate_key]) {
+                results.insert(pstate_key, (pstate & 0x07) as f64);
+            }
+        }
+        if filter.allows(&energy_key) {
+            if let Some(core_energy_raw) = read(AMD_MSR_CORE_ENERGY, core, &[&energy_key]) {
+                set_counter_range(&energy_key, counter_range);
+                results.insert(
+                    energy_key,
+                    (core_energy_raw & AMD_ENERGY_STATUS_MASK) as f64 * energy_unit_d,
+                );
+            }
+        }
+    }
 }
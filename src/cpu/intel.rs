@@ -1,73 +1,119 @@
-use serde;
-use serde::{Deserialize, Serialize};
-
+use once_cell::sync::OnceCell;
 use std::collections::HashMap;
+use std::io;
 
-use std::fs;
-use std::fs::DirEntry;
-use std::time::Instant;
-
-use super::msr::read_msr_on_core;
+use super::model::{Domain, Microarchitecture};
+use super::msr::{probe_msr, read_msr_on_core, MSR_PRIVILEGES, RAPL_UPDATE_RATE};
+use crate::wraparound::set_counter_range;
+use crate::metrics::MetricFilter;
+use crate::sensor::{warn_read_failure, SensorInfo};
 
 pub const INTEL_MSR_RAPL_POWER_UNIT: u32 = 0x606;
 pub const INTEL_MSR_RAPL_PKG: u32 = 0x611;
 pub const INTEL_MSR_RAPL_PP0: u32 = 0x639;
 pub const INTEL_MSR_RAPL_PP1: u32 = 0x641;
 pub const INTEL_MSR_RAPL_DRAM: u32 = 0x619;
+pub const INTEL_MSR_RAPL_PSYS: u32 = 0x64D;
 
-const INTEL_TIME_UNIT_MASK: u64 = 0xF0000; // Bits 19:16
 const INTEL_ENGERY_UNIT_MASK: u64 = 0x1F00; // Bits 12:8
-const INTEL_POWER_UNIT_MASK: u64 = 0x0F; // Bits 3:0
-
-const INTEL_TIME_UNIT_OFFSET: u32 = 0x10; // Offset 16
 const INTEL_ENGERY_UNIT_OFFSET: u32 = 0x08; // Offset 8
-const INTEL_POWER_UNIT_OFFSET: u32 = 0; // Offset 0
+
+// The energy status MSRs are 32 bits counters that wrap around
+const INTEL_ENERGY_STATUS_MASK: u64 = 0xFFFFFFFF;
 
 
-pub fn get_intel_cpu_counter(results: &mut HashMap<String, f64>) {
-    unsafe {
-        // --- Read units ---
-        // TODO: these values are constant, read them once and store them
+// The RAPL domains with their column name and energy status MSR.
+const INTEL_DOMAINS: [(Domain, &str, u32, &str); 5] = [
+    // DRAM = Energy consumed by the DRAM for the chip's memory controller.
+    (Domain::Dram, "DRAM_ENERGY (J)", INTEL_MSR_RAPL_DRAM, "DRAM"),
+    // PKG = CPU socket energy consumption
+    (Domain::Package, "PACKAGE_ENERGY (J)", INTEL_MSR_RAPL_PKG, "RAPL_PKG_ENERGY_STAT"),
+    // PP0 = CPU cores energy consumption
+    (Domain::Pp0, "PP0_ENERGY (J)", INTEL_MSR_RAPL_PP0, "PP0"),
+    // PP1 = Integrated GPU energy consumption
+    (Domain::Pp1, "PP1_ENERGY (J)", INTEL_MSR_RAPL_PP1, "PP1"),
+    // PSYS = Energy consumed by the whole platform (SoC, memory, storage...)
+    (Domain::Psys, "PSYS_ENERGY (J)", INTEL_MSR_RAPL_PSYS, "PSYS"),
+];
+
+/// Describes the RAPL domains supported by the CPU.
+pub fn probe_intel_sensors(arch: &Microarchitecture) -> Vec<SensorInfo> {
+    INTEL_DOMAINS
+        .iter()
+        .filter(|(domain, _, _, _)| arch.domains.contains(domain))
+        .filter_map(|(_, key, msr, _)| {
+            let readable = probe_msr(*msr)?;
+            let source = format!("RAPL MSR {:#x}", msr);
+            Some(SensorInfo::new(key, source, RAPL_UPDATE_RATE).requires(MSR_PRIVILEGES, readable))
+        })
+        .collect()
+}
+
+pub fn get_intel_cpu_counter(
+    arch: &Microarchitecture,
+    filter: &MetricFilter,
+    results: &mut HashMap<String, f64>,
+) {
+    // only read the MSRs of the selected domains the microarchitecture has
+    let domains: Vec<_> = INTEL_DOMAINS
+        .iter()
+        .filter(|(domain, key, _, _)| arch.domains.contains(domain) && filter.allows(key))
+        .collect();
+    if domains.is_empty() {
+        return;
+    }
+
+    let unit = match energy_unit() {
+        Ok(unit) => unit,
+        Err(e) => {
+            for (_, key, _, _) in domains {
+                warn_read_failure(key, format_args!("RAPL power unit: {}", e));
+            }
+            return;
+        }
+    };
+    for (domain, key, msr, name) in domains {
+        // some server parts count the DRAM energy in a fixed unit
+        let energy_unit_d = match domain {
+            Domain::Dram => arch.dram_energy_unit.unwrap_or(unit),
+            _ => unit,
+        };
+
+        // --- Read value ---
+        let raw = match unsafe { read_msr_on_core(*msr, 0) } {
+            Ok(raw) => raw,
+            Err(e) => {
+                warn_read_failure(key, format_args!("{}: {}", name, e));
+                continue;
+            }
+        };
+
+        // --- Convert & store ---
+        // convert the integer value to floating point value using the energy unit
+        // and store it in the results hashmap
+        results.insert(
+            key.to_string(),
+            (raw & INTEL_ENERGY_STATUS_MASK) as f64 * energy_unit_d,
+        );
+        set_counter_range(key, (INTEL_ENERGY_STATUS_MASK + 1) as f64 * energy_unit_d);
+    }
+}
+
+// Returns the energy unit of the RAPL MSRs in joules.
+// The unit is constant, it is read from the first package, the only one measured, once.
+fn energy_unit() -> io::Result<f64> {
+    static ENERGY_UNIT: OnceCell<f64> = OnceCell::new();
+    ENERGY_UNIT.get_or_try_init(|| {
         // The MSR only store integer values, but they represent floating point values.
         // The INTEL_MSR_RAPL_POWER_UNIT MSR contains the units for the RAPL MSRs for a specific intel chip.
         // it contains three units, which represent the time, power, and energy increments in the RAPL MSRs.
-        let core_energy_units: u64 = read_msr_on_core(INTEL_MSR_RAPL_POWER_UNIT, 0).unwrap();
-        
+        let core_energy_units: u64 = unsafe { read_msr_on_core(INTEL_MSR_RAPL_POWER_UNIT, 0) }?;
+
         // First, we extract the individual units using the masks and offsets.
         // Then we convert them to floating point values using the formula 0.5^x.
         // See Section 14.9.1 of the Intel Architectures Software Developer's Manual (Vol 3B) for more information.
         let energy_unit: u64 = (core_energy_units & INTEL_ENGERY_UNIT_MASK) >> INTEL_ENGERY_UNIT_OFFSET;
-        let energy_unit_d = 0.5f64.powf(energy_unit as f64);
-
-        // --- Read values ---
-        // PP0 = CPU cores energy consumption
-        let pp0 = read_msr_on_core(INTEL_MSR_RAPL_PP0, 0).expect("failed to read PP0");
-        // PP1 = Integrated GPU energy consumption
-        let pp1 = read_msr_on_core(INTEL_MSR_RAPL_PP1, 0).expect("failed to read PP1");
-        // PKG = CPU socket energy consumption
-        let pkg = read_msr_on_core(INTEL_MSR_RAPL_PKG, 0)
-            .expect("failed to read RAPL_PKG_ENERGY_STAT");
-        // DRAM = Energy consumed by the DRAM for the chip's memory controller.
-        let dram = read_msr_on_core(INTEL_MSR_RAPL_DRAM, 0).expect("failed to read DRAM");
-        
-        // --- Convert & store ---
-        // convert the integer values to floating point values using the energy unit
-        // and store them in the results hashmap
-        results.insert(
-            format!("DRAM_ENERGY (J)"),
-            dram as f64 * energy_unit_d,
-        );
-        results.insert(
-            format!("PACKAGE_ENERGY (J)"),
-            pkg as f64 * energy_unit_d,
-        );
-        results.insert(
-            format!("PP0_ENERGY (J)"),
-            pp0 as f64 * energy_unit_d,
-        );
-        results.insert(
-            format!("PP1_ENERGY (J)"),
-            pp1 as f64 * energy_unit_d,
-        );
-    }
+        Ok(0.5f64.powf(energy_unit as f64))
+    })
+    .copied()
 }
@@ -5,40 +5,134 @@ mod apple;
 #[cfg(not(target_os = "macos"))]
 mod intel;
 #[cfg(not(target_os = "macos"))]
+pub mod model;
+#[cfg(not(target_os = "macos"))]
 pub mod msr;
 
+#[cfg(not(target_os = "macos"))]
+use once_cell::sync::OnceCell;
 use std::collections::HashMap;
 use sysinfo::{CpuExt, System, SystemExt};
 
+#[cfg(not(target_os = "macos"))]
+use model::{microarchitecture, Backend, CpuId};
+
+use crate::metrics::{CpuDetail, MetricFilter};
+use crate::sensor::SensorInfo;
+
 pub fn get_number_cores(sys: &mut System) -> Option<usize> {
-    return sys.physical_core_count();
+    sys.physical_core_count()
 }
 
-pub fn get_cpu_usage(sys: &mut System, results: &mut HashMap<String, f64>) {
+/// Column names of the CPU usage and frequency metrics.
+pub fn cpu_usage_names(sys: &System, cpu_detail: CpuDetail) -> Vec<String> {
+    match cpu_detail {
+        CpuDetail::PerCore => (0..sys.cpus().len())
+            .flat_map(|i| [format!("CPU_USAGE_{i}"), format!("CPU_FREQUENCY_{i}")])
+            .collect(),
+        CpuDetail::Aggregated => vec!["CPU_USAGE".to_string(), "CPU_FREQUENCY".to_string()],
+    }
+}
+
+/// Collects the CPU usage and frequency, `force_refresh` refreshes the CPU data even if
+/// none of the metrics is selected because another consumer relies on it.
+pub fn get_cpu_usage(
+    sys: &mut System,
+    filter: &MetricFilter,
+    force_refresh: bool,
+    results: &mut HashMap<String, f64>,
+) {
+    let wanted = filter.allows_any(&cpu_usage_names(sys, filter.cpu_detail));
+    if !wanted && !force_refresh {
+        return;
+    }
     sys.refresh_cpu();
+    if !wanted {
+        return;
+    }
 
-    for (i, cpu) in sys.cpus().iter().enumerate() {
-        let key: String = format!("CPU_USAGE_{i}");
-        results.insert(key, cpu.cpu_usage().into());
-        let key: String = format!("CPU_FREQUENCY_{i}");
-        results.insert(key, cpu.frequency() as f64);
+    match filter.cpu_detail {
+        CpuDetail::PerCore => {
+            for (i, cpu) in sys.cpus().iter().enumerate() {
+                let key: String = format!("CPU_USAGE_{i}");
+                results.insert(key, cpu.cpu_usage().into());
+                let key: String = format!("CPU_FREQUENCY_{i}");
+                results.insert(key, cpu.frequency() as f64);
+            }
+        }
+        CpuDetail::Aggregated => {
+            let cpus = sys.cpus();
+            let frequency =
+                cpus.iter().map(|cpu| cpu.frequency() as f64).sum::<f64>() / cpus.len() as f64;
+            results.insert(
+                "CPU_USAGE".to_string(),
+                sys.global_cpu_info().cpu_usage().into(),
+            );
+            results.insert("CPU_FREQUENCY".to_string(), frequency);
+        }
     }
 }
 
+// Set once the unsupported CPU has been reported.
 #[cfg(not(target_os = "macos"))]
-pub fn get_cpu_counter(sys: &mut System, results: &mut HashMap<String, f64>) {
-    sys.refresh_cpu();
+static UNSUPPORTED_CPU_WARNING: OnceCell<()> = OnceCell::new();
 
-    let vendor = sys.global_cpu_info().vendor_id();
-    #[cfg(not(target_os = "macos"))]
-    if vendor == "GenuineIntel" {
-        intel::get_intel_cpu_counter(results);
-    } else if vendor == "AuthenticAMD" {
-        amd::get_amd_cpu_counter(sys, results);
+/// The CPU whose MSRs are read, the one of a replayed recording or of this machine.
+#[cfg(not(target_os = "macos"))]
+pub fn cpu_id(sys: &System) -> CpuId {
+    msr::msr_cpu().unwrap_or_else(|| {
+        let (family, model) = model::family_model();
+        CpuId {
+            vendor: sys.global_cpu_info().vendor_id().to_string(),
+            family,
+            model,
+        }
+    })
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn get_cpu_counter(sys: &mut System, filter: &MetricFilter, results: &mut HashMap<String, f64>) {
+    // the CPU does not change, the CPU information loaded at startup is enough
+    let cpu = cpu_id(sys);
+    match microarchitecture(&cpu) {
+        Some(arch) if arch.backend == Backend::Intel => {
+            intel::get_intel_cpu_counter(arch, filter, results)
+        }
+        Some(_) => amd::get_amd_cpu_counter(sys, filter, results),
+        None => {
+            UNSUPPORTED_CPU_WARNING.get_or_init(|| {
+                eprintln!(
+                    "[WARNING] The energy counters of {} family {:#x} CPUs are not supported, the CPU energy is not reported.",
+                    cpu.vendor, cpu.family
+                );
+            });
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn get_cpu_counter(_sys: &mut System, filter: &MetricFilter, results: &mut HashMap<String, f64>) {
+    apple::get_apple_cpu_counter(filter, results);
+}
+
+/// Describes the metrics provided by the energy counters of the CPU.
+#[cfg(not(target_os = "macos"))]
+pub fn probe_cpu_counter(sys: &mut System) -> Vec<SensorInfo> {
+    match microarchitecture(&cpu_id(sys)) {
+        Some(arch) if arch.backend == Backend::Intel => intel::probe_intel_sensors(arch),
+        Some(_) => amd::probe_amd_sensors(sys),
+        None => Vec::new(),
     }
 }
 
 #[cfg(target_os = "macos")]
-pub fn get_cpu_counter(sys: &mut System, results: &mut HashMap<String, f64>) {
-    apple::get_apple_cpu_counter(results);
+pub fn probe_cpu_counter(_sys: &mut System) -> Vec<SensorInfo> {
+    let mut results = HashMap::new();
+    apple::get_apple_cpu_counter(&MetricFilter::default(), &mut results);
+    let mut names: Vec<_> = results.into_keys().collect();
+    names.sort();
+    names
+        .iter()
+        .map(|name| SensorInfo::new(name, "SMC", "every sample"))
+        .collect()
 }
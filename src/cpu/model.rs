@@ -0,0 +1,236 @@
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+/// Identification of the CPU whose energy counters are read.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CpuId {
+    pub vendor: String,
+    /// Family and model as numbered by CPUID, 0 when unknown.
+    pub family: u32,
+    pub model: u32,
+}
+
+/// Energy domains of the RAPL interface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Domain {
+    Package,
+    Dram,
+    Pp0,
+    Pp1,
+    Psys,
+    Core,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Intel,
+    Amd,
+}
+
+/// The RAPL interface of a microarchitecture.
+#[derive(Debug)]
+pub struct Microarchitecture {
+    pub name: &'static str,
+    pub backend: Backend,
+    pub domains: &'static [Domain],
+    /// Energy unit of the DRAM domain in joules, when it does not use the unit of
+    /// MSR_RAPL_POWER_UNIT.
+    pub dram_energy_unit: Option<f64>,
+}
+
+use Domain::*;
+
+// The server parts since Haswell-EP count the DRAM energy in units of 15.3 µJ, see the
+// Intel Xeon E5-2600 v3 datasheet (Vol 2) and the intel_rapl driver of Linux.
+const SERVER_DRAM_UNIT: Option<f64> = Some(15.3e-6);
+
+const fn intel(
+    name: &'static str,
+    domains: &'static [Domain],
+    dram_energy_unit: Option<f64>,
+) -> Microarchitecture {
+    Microarchitecture {
+        name,
+        backend: Backend::Intel,
+        domains,
+        dram_energy_unit,
+    }
+}
+
+const fn amd(name: &'static str) -> Microarchitecture {
+    Microarchitecture {
+        name,
+        backend: Backend::Amd,
+        domains: &[Package, Core],
+        dram_energy_unit: None,
+    }
+}
+
+// The family 6 Intel models by CPUID model number.
+const INTEL_MODELS: [(&[u32], Microarchitecture); 20] = [
+    (&[0x2A], intel("Sandy Bridge", &[Package, Pp0, Pp1], None)),
+    (&[0x2D], intel("Sandy Bridge-EP", &[Package, Pp0, Dram], None)),
+    (&[0x3A], intel("Ivy Bridge", &[Package, Pp0, Pp1], None)),
+    (&[0x3E], intel("Ivy Bridge-EP", &[Package, Pp0, Dram], None)),
+    (&[0x3C, 0x45, 0x46], intel("Haswell", &[Package, Pp0, Pp1, Dram], None)),
+    (&[0x3F], intel("Haswell-EP", &[Package, Dram], SERVER_DRAM_UNIT)),
+    (&[0x3D, 0x47], intel("Broadwell", &[Package, Pp0, Pp1, Dram], None)),
+    (&[0x4F], intel("Broadwell-EP", &[Package, Dram], SERVER_DRAM_UNIT)),
+    (&[0x56], intel("Broadwell-DE", &[Package, Dram], SERVER_DRAM_UNIT)),
+    (&[0x4E, 0x5E], intel("Skylake", &[Package, Pp0, Pp1, Dram, Psys], None)),
+    // also Cascade Lake and Cooper Lake
+    (&[0x55], intel("Skylake-SP", &[Package, Dram], SERVER_DRAM_UNIT)),
+    (&[0x8E, 0x9E], intel("Kaby Lake, Coffee Lake", &[Package, Pp0, Pp1, Dram, Psys], None)),
+    (&[0xA5, 0xA6], intel("Comet Lake", &[Package, Pp0, Pp1, Dram, Psys], None)),
+    (&[0x7D, 0x7E], intel("Ice Lake", &[Package, Pp0, Pp1, Dram, Psys], None)),
+    (&[0x6A, 0x6C], intel("Ice Lake-SP", &[Package, Dram], SERVER_DRAM_UNIT)),
+    (&[0x8C, 0x8D], intel("Tiger Lake", &[Package, Pp0, Pp1, Dram, Psys], None)),
+    (&[0x97, 0x9A], intel("Alder Lake", &[Package, Pp0, Pp1, Dram, Psys], None)),
+    (&[0xB7, 0xBA, 0xBF], intel("Raptor Lake", &[Package, Pp0, Pp1, Dram, Psys], None)),
+    (&[0x8F], intel("Sapphire Rapids", &[Package, Dram, Psys], SERVER_DRAM_UNIT)),
+    (&[0xCF], intel("Emerald Rapids", &[Package, Dram, Psys], SERVER_DRAM_UNIT)),
+];
+
+// The models missing from the table read the domains of the first RAPL CPUs.
+const INTEL_GENERIC: Microarchitecture =
+    intel("unknown Intel", &[Package, Dram, Pp0, Pp1], None);
+
+// The AMD families by CPUID family number, Hygon Dhyana is a Zen derivative.
+const AMD_FAMILIES: [(u32, Microarchitecture); 4] = [
+    (0x17, amd("Zen, Zen 2")),
+    (0x18, amd("Hygon Dhyana")),
+    (0x19, amd("Zen 3, Zen 4")),
+    (0x1A, amd("Zen 5")),
+];
+
+// The RAPL MSRs of AMD appeared with Zen, the later families are assumed to keep them.
+const AMD_FIRST_RAPL_FAMILY: u32 = 0x17;
+const AMD_GENERIC: Microarchitecture = amd("unknown AMD");
+
+/// The microarchitecture of a CPU, or None if its energy counters are not supported.
+pub fn microarchitecture(cpu: &CpuId) -> Option<&'static Microarchitecture> {
+    match cpu.vendor.as_str() {
+        "GenuineIntel" => Some(
+            INTEL_MODELS
+                .iter()
+                .find(|(models, _)| cpu.family == 6 && models.contains(&cpu.model))
+                .map_or(&INTEL_GENERIC, |(_, arch)| arch),
+        ),
+        "AuthenticAMD" | "HygonGenuine" => {
+            // an unknown family, e.g. of an old recording, is assumed to have RAPL
+            if cpu.family != 0 && cpu.family < AMD_FIRST_RAPL_FAMILY {
+                return None;
+            }
+            Some(
+                AMD_FAMILIES
+                    .iter()
+                    .find(|(family, _)| *family == cpu.family)
+                    .map_or(&AMD_GENERIC, |(_, arch)| arch),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// The family and model of the CPU of this machine, read from CPUID once.
+pub fn family_model() -> (u32, u32) {
+    static FAMILY_MODEL: OnceCell<(u32, u32)> = OnceCell::new();
+    *FAMILY_MODEL.get_or_init(read_family_model)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn read_family_model() -> (u32, u32) {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::__cpuid;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::__cpuid;
+
+    // Leaf 1 holds the version information, see the CPUID instruction in Vol 2A of the
+    // Intel Architectures Software Developer's Manual.
+    // __cpuid is only safe to call in recent Rust versions
+    #[allow(unused_unsafe)]
+    let eax = unsafe { __cpuid(1) }.eax;
+    let base_family = (eax >> 8) & 0xF;
+    let base_model = (eax >> 4) & 0xF;
+    let family = if base_family == 0xF {
+        base_family + ((eax >> 20) & 0xFF)
+    } else {
+        base_family
+    };
+    let model = if base_family == 0x6 || base_family == 0xF {
+        base_model | (((eax >> 16) & 0xF) << 4)
+    } else {
+        base_model
+    };
+    (family, model)
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn read_family_model() -> (u32, u32) {
+    (0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu(vendor: &str, family: u32, model: u32) -> CpuId {
+        CpuId {
+            vendor: vendor.to_string(),
+            family,
+            model,
+        }
+    }
+
+    #[test]
+    fn known_intel_model() {
+        let arch = microarchitecture(&cpu("GenuineIntel", 6, 0x55)).unwrap();
+        assert_eq!(arch.name, "Skylake-SP");
+        assert_eq!(arch.backend, Backend::Intel);
+        assert_eq!(arch.domains, &[Package, Dram]);
+        assert_eq!(arch.dram_energy_unit, Some(15.3e-6));
+    }
+
+    #[test]
+    fn unknown_intel_model_is_generic() {
+        for cpu in [cpu("GenuineIntel", 6, 0xFF), cpu("GenuineIntel", 0x13, 0x01)] {
+            let arch = microarchitecture(&cpu).unwrap();
+            assert_eq!(arch.name, INTEL_GENERIC.name);
+            assert_eq!(arch.domains, INTEL_GENERIC.domains);
+            assert_eq!(arch.dram_energy_unit, None);
+        }
+    }
+
+    #[test]
+    fn hygon_dhyana() {
+        let arch = microarchitecture(&cpu("HygonGenuine", 0x18, 0)).unwrap();
+        assert_eq!(arch.name, "Hygon Dhyana");
+        assert_eq!(arch.backend, Backend::Amd);
+    }
+
+    #[test]
+    fn amd_families() {
+        // the families before Zen have no RAPL MSRs
+        assert!(microarchitecture(&cpu("AuthenticAMD", 0x15, 0x30)).is_none());
+        assert!(microarchitecture(&cpu("AuthenticAMD", 0x16, 0x00)).is_none());
+        assert_eq!(
+            microarchitecture(&cpu("AuthenticAMD", 0x19, 0x21)).unwrap().name,
+            "Zen 3, Zen 4"
+        );
+        assert_eq!(
+            microarchitecture(&cpu("AuthenticAMD", 0x1B, 0)).unwrap().name,
+            AMD_GENERIC.name
+        );
+        // the family of old recordings is unknown
+        assert_eq!(
+            microarchitecture(&cpu("AuthenticAMD", 0, 0)).unwrap().name,
+            AMD_GENERIC.name
+        );
+    }
+
+    #[test]
+    fn unknown_vendor() {
+        assert!(microarchitecture(&cpu("CentaurHauls", 6, 0x0F)).is_none());
+        assert!(microarchitecture(&CpuId::default()).is_none());
+    }
+}
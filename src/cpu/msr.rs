@@ -1,23 +1,81 @@
-use once_cell::sync::OnceCell;
-use std::sync::Once;
+use once_cell::sync::Lazy;
+use std::io;
+use std::sync::Mutex;
+
+use super::model::CpuId;
 
 #[cfg(target_os = "linux")]
 pub mod linux;
+pub mod record;
 #[cfg(target_os = "windows")]
 pub mod windows;
 
 // https://github.com/cs-23-pt-9-01/rapl-interface
 
-pub fn start_rapl() {    
+/// Privileges needed to read the MSRs.
+#[cfg(target_os = "linux")]
+pub const MSR_PRIVILEGES: &str = "root, CAP_SYS_RAWIO or msr-safe";
+#[cfg(target_os = "windows")]
+pub const MSR_PRIVILEGES: &str = "administrator";
+
+/// The RAPL energy counters are updated about every millisecond.
+pub const RAPL_UPDATE_RATE: &str = "~1 ms";
+
+/// Source of the values of the MSRs, replaced to record or replay the registers of a run.
+pub trait MsrReader: Send {
+    /// Reads a MSR of a core.
+    fn read(&mut self, msr: u32, core: u32) -> io::Result<u64>;
+
+    /// The CPU the registers belong to, when it is not the CPU of this machine.
+    fn cpu(&self) -> Option<CpuId> {
+        None
+    }
+
+    /// Writes the buffered data, called at the end of the run.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+static READER: Lazy<Mutex<Box<dyn MsrReader>>> = Lazy::new(|| Mutex::new(system_reader()));
+
+/// The reader of the MSRs of this machine.
+pub fn system_reader() -> Box<dyn MsrReader> {
+    #[cfg(target_os = "linux")]
+    return Box::new(linux::LinuxMsr::default());
+    #[cfg(target_os = "windows")]
+    return Box::new(windows::WindowsMsr);
+}
+
+/// Replaces the reader used by the CPU backends.
+pub fn set_msr_reader(reader: Box<dyn MsrReader>) {
+    *READER.lock().unwrap() = reader;
+}
+
+/// The CPU whose registers are read, if it is not the CPU of this machine.
+pub fn msr_cpu() -> Option<CpuId> {
+    READER.lock().unwrap().cpu()
+}
+
+pub fn flush_msr_reader() -> io::Result<()> {
+    READER.lock().unwrap().flush()
+}
+
+pub fn start_rapl() {
     #[cfg(target_os = "windows")]
     windows::start_rapl_impl();
 }
 
 pub unsafe fn read_msr_on_core(msr: u32, core: u32) -> Result<u64, std::io::Error> {
-    unsafe {
-        #[cfg(target_os = "windows")]
-        return windows::read_msr_on_core(msr, core);
-        #[cfg(target_os = "linux")]
-        return linux::read_msr_on_core(msr, core);
+    READER.lock().unwrap().read(msr, core)
+}
+
+/// Returns whether a MSR of the first core can be read, or None if it does not exist
+/// or the MSR driver is not available.
+pub fn probe_msr(msr: u32) -> Option<bool> {
+    match unsafe { read_msr_on_core(msr, 0) } {
+        Ok(_) => Some(true),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Some(false),
+        Err(_) => None,
     }
 }
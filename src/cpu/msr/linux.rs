@@ -1,27 +1,91 @@
 #![cfg(target_os = "linux")]
 
-use once_cell::sync::OnceCell;
-use std::{ffi::CString, sync::Once};
-use std::{
-    fs::{File, OpenOptions},
-};
+use std::collections::hash_map::{Entry, HashMap};
+use std::fs::File;
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::FileExt;
 
-use std::io::Read;
-use std::io::Seek;
-use std::io::SeekFrom;
+use super::MsrReader;
 
+// Environment variable overriding the path of the MSR devices, `{cpu}` is replaced by
+// the index of the CPU, e.g. `/tmp/msr/{cpu}`.
+const MSR_DEVICE_ENV: &str = "ENERGIBRIDGE_MSR_DEVICE";
 
-pub fn read_msr_on_core(msr: u32, core: u32) -> Result<u64, std::io::Error> {
-    let mut file = File::open(format!("/dev/cpu/{}/msr", core))?;
+// Registers read by EnergiBridge, which msr-safe must allow.
+const MSR_ALLOWLIST: [(u32, &str); 11] = [
+    (0x606, "MSR_RAPL_POWER_UNIT"),
+    (0x611, "MSR_PKG_ENERGY_STATUS"),
+    (0x619, "MSR_DRAM_ENERGY_STATUS"),
+    (0x639, "MSR_PP0_ENERGY_STATUS"),
+    (0x641, "MSR_PP1_ENERGY_STATUS"),
+    (0x64D, "MSR_PLATFORM_ENERGY_STATUS"),
+    (0xC0010063, "AMD P-state status"),
+    (0xC0010293, "AMD hardware P-state status"),
+    (0xC0010299, "AMD RAPL power unit"),
+    (0xC001029A, "AMD core energy status"),
+    (0xC001029B, "AMD package energy status"),
+];
 
-    // Seek to the MSR address
-    file.seek(SeekFrom::Start(u64::from(msr)))?;
+// The device of a CPU.
+struct Device {
+    file: File,
+    // msr-safe only reads the registers of its allowlist
+    safe: bool,
+}
+
+/// Reads the MSRs through the devices of the msr or msr-safe drivers.
+#[derive(Default)]
+pub struct LinuxMsr {
+    // opened on their first read and kept open for the run
+    devices: HashMap<u32, Device>,
+}
+
+// Opens the msr device of a CPU, or its msr-safe device if the former is not accessible.
+fn open(core: u32) -> Result<Device, Error> {
+    if let Ok(path) = std::env::var(MSR_DEVICE_ENV) {
+        let file = File::open(path.replace("{cpu}", &core.to_string()))?;
+        return Ok(Device { file, safe: false });
+    }
+    match File::open(format!("/dev/cpu/{}/msr", core)) {
+        Ok(file) => Ok(Device { file, safe: false }),
+        Err(e) => match File::open(format!("/dev/cpu/{}/msr_safe", core)) {
+            Ok(file) => Ok(Device { file, safe: true }),
+            Err(_) => Err(e),
+        },
+    }
+}
+
+impl MsrReader for LinuxMsr {
+    fn read(&mut self, msr: u32, core: u32) -> Result<u64, Error> {
+        let device = match self.devices.entry(core) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(open(core)?),
+        };
 
-    // Read the 8-byte MSR value
-    let mut value_bytes = [0u8; 8];
-    file.read_exact(&mut value_bytes)?;
+        // The offset of the read is the MSR address, read the 8-byte value at once
+        let mut value_bytes = [0u8; 8];
+        match device.file.read_exact_at(&mut value_bytes, u64::from(msr)) {
+            Ok(()) => {}
+            Err(e) if device.safe && e.kind() == ErrorKind::PermissionDenied => {
+                return Err(Error::new(ErrorKind::PermissionDenied, allowlist_error(msr)));
+            }
+            Err(e) => return Err(e),
+        }
 
-    let value = u64::from_le_bytes(value_bytes);
+        let value = u64::from_le_bytes(value_bytes);
+
+        Ok(value)
+    }
+}
 
-    Ok(value)
+fn allowlist_error(msr: u32) -> String {
+    let entries: Vec<String> = MSR_ALLOWLIST
+        .iter()
+        .map(|(msr, name)| format!("0x{:08X} 0x0000000000000000 # {}", msr, name))
+        .collect();
+    format!(
+        "the MSR {:#x} is not in the msr-safe allowlist, EnergiBridge needs these entries:\n{}",
+        msr,
+        entries.join("\n")
+    )
 }
@@ -0,0 +1,209 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::MsrReader;
+use crate::cpu::model::CpuId;
+
+// First lines of a recording, describing the CPU.
+const VENDOR_PREFIX: &str = "# vendor: ";
+const FAMILY_PREFIX: &str = "# family: ";
+const MODEL_PREFIX: &str = "# model: ";
+const HEADER: &str = "Time,CPU,MSR,Value";
+
+/// Logs every MSR read during a run to a CSV file that `MsrReplay` feeds back.
+///
+/// Each line holds the Unix time in milliseconds, the core, the address of the MSR in
+/// hexadecimal and its value.
+pub struct MsrRecorder {
+    reader: Box<dyn MsrReader>,
+    output: BufWriter<File>,
+}
+
+impl MsrRecorder {
+    /// Records the values read by `reader` from `cpu`.
+    pub fn create(path: &Path, reader: Box<dyn MsrReader>, cpu: &CpuId) -> io::Result<Self> {
+        let mut output = BufWriter::new(File::create(path)?);
+        writeln!(output, "{}{}", VENDOR_PREFIX, cpu.vendor)?;
+        writeln!(output, "{}{}", FAMILY_PREFIX, cpu.family)?;
+        writeln!(output, "{}{}", MODEL_PREFIX, cpu.model)?;
+        writeln!(output, "{}", HEADER)?;
+        Ok(MsrRecorder { reader, output })
+    }
+}
+
+impl MsrReader for MsrRecorder {
+    fn read(&mut self, msr: u32, core: u32) -> io::Result<u64> {
+        let value = self.reader.read(msr, core)?;
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        writeln!(self.output, "{},{},{:#x},{}", time, core, msr, value)?;
+        Ok(value)
+    }
+
+    fn cpu(&self) -> Option<CpuId> {
+        self.reader.cpu()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Replays the MSR values of a recording, in the order they were read.
+///
+/// Each register returns its recorded values one after the other and then keeps its last
+/// value, the registers that were not recorded are reported as missing. The family and
+/// model of the recordings made before they were logged are unknown.
+pub struct MsrReplay {
+    cpu: Option<CpuId>,
+    values: HashMap<(u32, u32), VecDeque<u64>>,
+}
+
+impl MsrReplay {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let invalid = |number: usize, line: &str| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid MSR record on line {}: {}", number, line),
+            )
+        };
+        let mut cpu: Option<CpuId> = None;
+        let mut values: HashMap<(u32, u32), VecDeque<u64>> = HashMap::new();
+        for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if let Some(name) = line.strip_prefix(VENDOR_PREFIX) {
+                cpu.get_or_insert_with(CpuId::default).vendor = name.trim().to_string();
+                continue;
+            }
+            if let Some(family) = line.strip_prefix(FAMILY_PREFIX) {
+                let family = family.trim().parse().map_err(|_| invalid(index + 1, &line))?;
+                cpu.get_or_insert_with(CpuId::default).family = family;
+                continue;
+            }
+            if let Some(model) = line.strip_prefix(MODEL_PREFIX) {
+                let model = model.trim().parse().map_err(|_| invalid(index + 1, &line))?;
+                cpu.get_or_insert_with(CpuId::default).model = model;
+                continue;
+            }
+            if line.trim().is_empty() || line == HEADER {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [_, core, msr, value] = fields[..] else {
+                return Err(invalid(index + 1, &line));
+            };
+            let core = core.parse().map_err(|_| invalid(index + 1, &line))?;
+            let msr = u32::from_str_radix(msr.trim_start_matches("0x"), 16)
+                .map_err(|_| invalid(index + 1, &line))?;
+            let value = value.parse().map_err(|_| invalid(index + 1, &line))?;
+            values.entry((core, msr)).or_default().push_back(value);
+        }
+        Ok(MsrReplay { cpu, values })
+    }
+}
+
+impl MsrReader for MsrReplay {
+    fn read(&mut self, msr: u32, core: u32) -> io::Result<u64> {
+        let values = self.values.get_mut(&(core, msr)).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("the MSR {:#x} of core {} was not recorded", msr, core),
+            )
+        })?;
+        if values.len() > 1 {
+            Ok(values.pop_front().unwrap())
+        } else {
+            Ok(values[0])
+        }
+    }
+
+    fn cpu(&self) -> Option<CpuId> {
+        self.cpu.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A reader returning a counter incremented on every read.
+    struct Counter(u64);
+
+    impl MsrReader for Counter {
+        fn read(&mut self, msr: u32, _core: u32) -> io::Result<u64> {
+            self.0 += 1;
+            Ok(msr as u64 * 100 + self.0)
+        }
+
+        fn cpu(&self) -> Option<CpuId> {
+            None
+        }
+    }
+
+    fn recording(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("energibridge-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn replays_the_values_in_order_then_the_last_one() {
+        let path = recording("replay.msr.csv");
+        std::fs::write(
+            &path,
+            "# vendor: GenuineIntel\n# family: 6\n# model: 85\nTime,CPU,MSR,Value\n\
+             1,0,0x611,10\n2,0,0x619,7\n3,0,0x611,20\n4,1,0x611,5\n5,0,0x611,30\n",
+        )
+        .unwrap();
+        let mut replay = MsrReplay::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let cpu = replay.cpu().unwrap();
+        assert_eq!((cpu.vendor.as_str(), cpu.family, cpu.model), ("GenuineIntel", 6, 85));
+        let reads: Vec<u64> = (0..5).map(|_| replay.read(0x611, 0).unwrap()).collect();
+        assert_eq!(reads, [10, 20, 30, 30, 30]);
+        // the registers and the cores have their own queue
+        assert_eq!(replay.read(0x619, 0).unwrap(), 7);
+        assert_eq!(replay.read(0x619, 0).unwrap(), 7);
+        assert_eq!(replay.read(0x611, 1).unwrap(), 5);
+        let missing = replay.read(0x606, 0).unwrap_err();
+        assert_eq!(missing.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn replays_a_recording() {
+        let path = recording("record.msr.csv");
+        let cpu = CpuId {
+            vendor: "AuthenticAMD".to_string(),
+            family: 0x19,
+            model: 0x21,
+        };
+        let mut recorder = MsrRecorder::create(&path, Box::new(Counter(0)), &cpu).unwrap();
+        let recorded: Vec<u64> = [0x611, 0x619, 0x611]
+            .iter()
+            .map(|msr| recorder.read(*msr, 0).unwrap())
+            .collect();
+        recorder.flush().unwrap();
+        drop(recorder);
+
+        let mut replay = MsrReplay::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.cpu(), Some(cpu));
+        assert_eq!(replay.read(0x611, 0).unwrap(), recorded[0]);
+        assert_eq!(replay.read(0x611, 0).unwrap(), recorded[2]);
+        assert_eq!(replay.read(0x619, 0).unwrap(), recorded[1]);
+    }
+
+    #[test]
+    fn rejects_an_invalid_line() {
+        let path = recording("invalid.msr.csv");
+        std::fs::write(&path, "Time,CPU,MSR,Value\n1,0,0x611\n").unwrap();
+        let error = MsrReplay::open(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 2"));
+    }
+}
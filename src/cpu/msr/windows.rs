@@ -113,4 +113,12 @@ pub unsafe fn read_msr_on_core(msr: u32, core: u32) -> Result<u64, std::io::Erro
     // TODO: Consider using lp_bytes_returned for error handling or logging it, it is supposed to return 8 bytes on success
     //println!("lp_bytes_returned: {}", lp_bytes_returned);
     Ok(u64::from_le_bytes(output_data))
-}
\ No newline at end of file
+}
+/// Reads the MSRs through the WinRing0 driver.
+pub struct WindowsMsr;
+
+impl super::MsrReader for WindowsMsr {
+    fn read(&mut self, msr: u32, core: u32) -> Result<u64, std::io::Error> {
+        unsafe { read_msr_on_core(msr, core) }
+    }
+}
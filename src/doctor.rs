@@ -0,0 +1,363 @@
+#[cfg(not(target_os = "macos"))]
+use sysinfo::{System, SystemExt};
+
+/// Outcome of a check, a warning only affects some of the metrics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// A prerequisite of EnergiBridge checked on this machine.
+struct Check {
+    name: &'static str,
+    status: Status,
+    message: String,
+    /// Commands solving the problem.
+    fix: Vec<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, message: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Ok,
+            message: message.into(),
+            fix: Vec::new(),
+        }
+    }
+
+    fn problem(name: &'static str, status: Status, message: impl Into<String>, fix: &[&str]) -> Self {
+        Check {
+            name,
+            status,
+            message: message.into(),
+            fix: fix.iter().map(|command| command.to_string()).collect(),
+        }
+    }
+
+    fn print(&self) {
+        let status = match self.status {
+            Status::Ok => "[OK]",
+            Status::Warning => "[WARNING]",
+            Status::Error => "[ERROR]",
+        };
+        println!("{} {}: {}", status, self.name, self.message);
+        if !self.fix.is_empty() {
+            println!("    Fix:");
+            for command in &self.fix {
+                println!("        {}", command);
+            }
+        }
+    }
+}
+
+/// Checks the setup needed to read the sensors, prints what is wrong and how to fix it.
+///
+/// Returns false if a check failed.
+pub fn run() -> bool {
+    let mut checks = Vec::new();
+    #[cfg(not(target_os = "macos"))]
+    checks.push(check_cpu());
+    #[cfg(target_os = "linux")]
+    checks.extend(linux::checks());
+    #[cfg(not(target_os = "linux"))]
+    println!("Only the Linux setup of the CPU sensors can be checked.");
+    #[cfg(not(target_os = "macos"))]
+    checks.push(check_nvml());
+
+    for check in &checks {
+        check.print();
+    }
+    !checks.iter().any(|check| check.status == Status::Error)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn check_cpu() -> Check {
+    let mut sys = System::new();
+    sys.refresh_cpu();
+    let cpu = crate::cpu::cpu_id(&sys);
+    let description = format!("{} family {:#x} model {:#x}", cpu.vendor, cpu.family, cpu.model);
+    match crate::cpu::model::microarchitecture(&cpu) {
+        Some(arch) => Check::ok("CPU", format!("{} ({})", description, arch.name)),
+        None => Check::problem(
+            "CPU",
+            Status::Warning,
+            format!("the energy counters of {} are not supported, the CPU energy is not reported", description),
+            &[],
+        ),
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn check_nvml() -> Check {
+    match nvml_wrapper::NVML::init() {
+        Ok(nvml) => {
+            let version = nvml.sys_driver_version().unwrap_or_default();
+            let count = nvml.device_count().unwrap_or(0);
+            Check::ok("NVML", format!("driver {}, {} GPU(s)", version, count))
+        }
+        Err(e) => Check::problem(
+            "NVML",
+            Status::Warning,
+            format!(
+                "the NVIDIA Management Library cannot be loaded ({}), the NVIDIA GPUs are not measured with --gpu",
+                e
+            ),
+            &["install the NVIDIA driver, which provides libnvidia-ml"],
+        ),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CString;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+
+    use super::{Check, Status};
+
+    const CAP_SYS_RAWIO: u32 = 17;
+    const POWERCAP: &str = "/sys/class/powercap";
+    const PERF_EVENT_PARANOID: &str = "/proc/sys/kernel/perf_event_paranoid";
+
+    pub fn checks() -> Vec<Check> {
+        let root = unsafe { libc::geteuid() } == 0;
+        let mut checks = check_msr(Path::new("/dev/cpu"), root, rawio_granted());
+        checks.push(check_powercap(root));
+        checks.push(check_perf_event_paranoid(root));
+        checks
+    }
+
+    // The MSRs are read from the msr nodes when they are usable and from the msr-safe
+    // ones otherwise, as LinuxMsr does.
+    fn check_msr(dev: &Path, root: bool, rawio: bool) -> Vec<Check> {
+        let nodes = msr_nodes(dev, "msr");
+        let safe_nodes = msr_nodes(dev, "msr_safe");
+        if nodes.is_empty() && safe_nodes.is_empty() {
+            return vec![Check::problem(
+                "msr module",
+                Status::Error,
+                "/dev/cpu/*/msr does not exist, the msr kernel module is not loaded",
+                &[
+                    "sudo modprobe msr",
+                    "echo msr | sudo tee /etc/modules-load.d/msr.conf  # load it at boot",
+                ],
+            )];
+        }
+        let msr = [check_nodes(&nodes, "msr", root), check_capability(root, rawio)];
+        let usable = msr.iter().all(|check| check.status == Status::Ok);
+        if !safe_nodes.is_empty() && (nodes.is_empty() || !usable) {
+            // msr-safe gives access to an allowlist of registers without CAP_SYS_RAWIO
+            let safe = check_nodes(&safe_nodes, "msr_safe", root);
+            if nodes.is_empty() || safe.status == Status::Ok {
+                return vec![Check::ok("msr module", "msr-safe loaded"), safe];
+            }
+        }
+        let mut checks = vec![Check::ok("msr module", "loaded")];
+        checks.extend(msr);
+        checks
+    }
+
+    // The MSR device nodes of the CPUs with this name, sorted by CPU.
+    fn msr_nodes(dev: &Path, name: &str) -> Vec<PathBuf> {
+        let mut nodes: Vec<(usize, PathBuf)> = fs::read_dir(dev)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| {
+                        let cpu = entry.file_name().to_str()?.parse().ok()?;
+                        Some((cpu, entry.path().join(name)))
+                    })
+                    .filter(|(_, path)| path.exists())
+                    .collect()
+            })
+            .unwrap_or_default();
+        nodes.sort();
+        nodes.into_iter().map(|(_, path)| path).collect()
+    }
+
+    fn readable(path: &Path) -> bool {
+        let Ok(path) = CString::new(path.as_os_str().as_encoded_bytes()) else {
+            return false;
+        };
+        unsafe { libc::access(path.as_ptr(), libc::R_OK) == 0 }
+    }
+
+    fn check_nodes(nodes: &[PathBuf], name: &str, root: bool) -> Check {
+        let denied: Vec<&PathBuf> = nodes.iter().filter(|node| !readable(node)).collect();
+        if root || denied.is_empty() {
+            return Check::ok("msr device nodes", format!("{} readable", nodes.len()));
+        }
+        let description = match fs::metadata(denied[0]) {
+            Ok(metadata) => format!(
+                ", {} belongs to uid {} and gid {} with mode {:o}",
+                denied[0].display(),
+                metadata.uid(),
+                metadata.gid(),
+                metadata.mode() & 0o777
+            ),
+            Err(_) => String::new(),
+        };
+        Check::problem(
+            "msr device nodes",
+            Status::Error,
+            format!(
+                "{} of {} nodes are not readable by the current user{}",
+                denied.len(),
+                nodes.len(),
+                description
+            ),
+            &[
+                "sudo groupadd -f msr && sudo usermod -aG msr $USER  # then log in again",
+                &format!(
+                    "sudo chgrp msr /dev/cpu/*/{0} && sudo chmod g+r /dev/cpu/*/{0}  # reset at boot",
+                    name
+                ),
+            ],
+        )
+    }
+
+    // Whether the process has CAP_SYS_RAWIO.
+    fn rawio_granted() -> bool {
+        let capabilities = fs::read_to_string("/proc/self/status")
+            .ok()
+            .and_then(|status| {
+                let line = status.lines().find(|line| line.starts_with("CapEff:"))?;
+                u64::from_str_radix(line["CapEff:".len()..].trim(), 16).ok()
+            })
+            .unwrap_or(0);
+        capabilities & (1 << CAP_SYS_RAWIO) != 0
+    }
+
+    // The msr driver also requires CAP_SYS_RAWIO from the processes that are not root.
+    fn check_capability(root: bool, rawio: bool) -> Check {
+        if root {
+            return Check::ok("CAP_SYS_RAWIO", "running as root");
+        }
+        if rawio {
+            return Check::ok("CAP_SYS_RAWIO", "granted");
+        }
+        let binary = std::env::current_exe()
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|_| "energibridge".to_string());
+        Check::problem(
+            "CAP_SYS_RAWIO",
+            Status::Error,
+            "the binary does not have the capability to read the MSRs, it must be set again when the binary is moved or rebuilt",
+            &[&format!("sudo setcap cap_sys_rawio=ep {}", binary)],
+        )
+    }
+
+    fn check_powercap(root: bool) -> Check {
+        let counters: Vec<PathBuf> = fs::read_dir(POWERCAP)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path().join("energy_uj"))
+                    .filter(|path| path.exists())
+                    .collect()
+            })
+            .unwrap_or_default();
+        if counters.is_empty() {
+            return Check::problem(
+                "powercap",
+                Status::Warning,
+                format!("{} does not expose any energy counter", POWERCAP),
+                &[],
+            );
+        }
+        if root || counters.iter().all(|path| readable(path)) {
+            return Check::ok("powercap", format!("{} energy counters readable", counters.len()));
+        }
+        Check::problem(
+            "powercap",
+            Status::Warning,
+            "the energy counters are only readable by root since Linux 5.10",
+            &["sudo chmod o+r /sys/class/powercap/*/energy_uj  # reset at boot"],
+        )
+    }
+
+    fn check_perf_event_paranoid(root: bool) -> Check {
+        let Some(level) = fs::read_to_string(PERF_EVENT_PARANOID)
+            .ok()
+            .and_then(|level| level.trim().parse::<i32>().ok())
+        else {
+            return Check::problem(
+                "perf_event_paranoid",
+                Status::Warning,
+                format!("{} cannot be read, perf events are not supported", PERF_EVENT_PARANOID),
+                &[],
+            );
+        };
+        if root || level <= 0 {
+            return Check::ok("perf_event_paranoid", format!("{}", level));
+        }
+        Check::problem(
+            "perf_event_paranoid",
+            Status::Warning,
+            format!(
+                "{}, the system-wide RAPL perf events (power/energy-*) are reserved to root",
+                level
+            ),
+            &["sudo sysctl -w kernel.perf_event_paranoid=0"],
+        )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // A /dev/cpu directory with the msr and msr_safe nodes of two CPUs.
+        fn dev_cpu(name: &str) -> PathBuf {
+            let dev = std::env::temp_dir().join(format!("energibridge-{}-{}", std::process::id(), name));
+            for cpu in ["0", "1"] {
+                fs::create_dir_all(dev.join(cpu)).unwrap();
+                fs::write(dev.join(cpu).join("msr"), "").unwrap();
+                fs::write(dev.join(cpu).join("msr_safe"), "").unwrap();
+            }
+            dev
+        }
+
+        fn summary(checks: &[Check]) -> Vec<(&str, Status)> {
+            checks.iter().map(|check| (check.name, check.status)).collect()
+        }
+
+        #[test]
+        fn msr_safe_is_used_without_cap_sys_rawio() {
+            let dev = dev_cpu("msr-safe");
+            let checks = check_msr(&dev, false, false);
+            fs::remove_dir_all(&dev).unwrap();
+            assert_eq!(
+                summary(&checks),
+                [("msr module", Status::Ok), ("msr device nodes", Status::Ok)]
+            );
+            assert_eq!(checks[0].message, "msr-safe loaded");
+        }
+
+        #[test]
+        fn msr_is_used_with_cap_sys_rawio() {
+            let dev = dev_cpu("msr");
+            let checks = check_msr(&dev, false, true);
+            fs::remove_dir_all(&dev).unwrap();
+            assert_eq!(
+                summary(&checks),
+                [
+                    ("msr module", Status::Ok),
+                    ("msr device nodes", Status::Ok),
+                    ("CAP_SYS_RAWIO", Status::Ok)
+                ]
+            );
+            assert_eq!(checks[0].message, "loaded");
+        }
+
+        #[test]
+        fn missing_msr_module() {
+            let dev = std::env::temp_dir().join(format!("energibridge-{}-empty", std::process::id()));
+            let checks = check_msr(&dev, false, false);
+            assert_eq!(summary(&checks), [("msr module", Status::Error)]);
+        }
+    }
+}
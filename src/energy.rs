@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::overhead;
+use crate::wraparound::counter_delta;
+
+// Domains used for the legacy "Energy consumption" summary line, by order of preference.
+const PRIMARY_DOMAINS: [&str; 4] = [
+    "CPU_POWER (Watts)",
+    "SYSTEM_POWER (Watts)",
+    "CPU_ENERGY (J)",
+    "PACKAGE_ENERGY (J)",
+];
+
+/// Returns true if the metric is a cumulative energy counter.
+pub fn is_energy(key: &str) -> bool {
+    // the energy attributed to EnergiBridge is a share of the other domains, counting it
+    // as a domain of its own would count it twice
+    key.ends_with("(J)") && key != overhead::ENERGY_KEY
+}
+
+/// Returns the factor to convert the metric to Watts if it is a power reading.
+pub fn power_factor(key: &str) -> Option<f64> {
+    if key.ends_with("(Watts)") {
+        Some(1.0)
+    } else if key.ends_with("(mWatts)") {
+        Some(0.001)
+    } else {
+        None
+    }
+}
+
+/// The domain used to report the energy consumption of the whole run.
+pub fn primary_domain(results: &HashMap<String, f64>) -> Option<&'static str> {
+    PRIMARY_DOMAINS
+        .into_iter()
+        .find(|key| results.contains_key(*key))
+}
+
+/// Accumulates the energy consumed by each domain across samples.
+///
+/// Energy counters are differentiated and power readings are integrated over the
+/// interval, so both end up as joules consumed since the first sample.
+#[derive(Default)]
+pub struct EnergyAccumulator {
+    previous: HashMap<String, f64>,
+    totals: HashMap<String, f64>,
+}
+
+impl EnergyAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a new sample taken `elapsed` after the previous one and returns the
+    /// energy consumed by each domain during that interval.
+    pub fn update(
+        &mut self,
+        results: &HashMap<String, f64>,
+        elapsed: Duration,
+    ) -> HashMap<String, f64> {
+        let mut deltas = HashMap::new();
+        for (key, value) in results {
+            let delta = if is_energy(key) {
+                match self.previous.get(key) {
+                    Some(previous) => counter_delta(key, *previous, *value),
+                    None => continue,
+                }
+            } else if let Some(factor) = power_factor(key) {
+                value * factor * elapsed.as_secs_f64()
+            } else {
+                continue;
+            };
+            *self.totals.entry(key.clone()).or_insert(0.0) += delta;
+            deltas.insert(key.clone(), delta);
+        }
+        // a counter missing from a sample is differentiated from its last value
+        self.previous
+            .extend(results.iter().map(|(key, value)| (key.clone(), *value)));
+        deltas
+    }
+
+    pub fn totals(&self) -> &HashMap<String, f64> {
+        &self.totals
+    }
+}
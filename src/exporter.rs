@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Response, Server};
+
+use crate::energy::{is_energy, power_factor};
+use crate::sensor::Sensor;
+
+/// Serves the latest sample in the Prometheus text format on `/metrics`.
+pub struct Exporter {
+    page: Arc<Mutex<String>>,
+}
+
+impl Exporter {
+    pub fn start(address: &str) -> io::Result<Exporter> {
+        let server = Server::http(address)
+            .map_err(|e| io::Error::new(io::ErrorKind::AddrNotAvailable, e.to_string()))?;
+        let page = Arc::new(Mutex::new(String::new()));
+        let served = page.clone();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let response = if request.url() == "/metrics" {
+                    let content_type =
+                        Header::from_bytes("Content-Type", "text/plain; version=0.0.4").unwrap();
+                    Response::from_string(served.lock().unwrap().clone())
+                        .with_header(content_type)
+                } else {
+                    Response::from_string("Not Found").with_status_code(404)
+                };
+                let _ = request.respond(response);
+            }
+        });
+        Ok(Exporter { page })
+    }
+
+    /// Publishes a new sample, `totals` is the energy consumed by each domain so far.
+    pub fn update(&self, results: &HashMap<String, f64>, totals: &HashMap<String, f64>) {
+        *self.page.lock().unwrap() = render(results, totals);
+    }
+}
+
+struct Family {
+    kind: &'static str,
+    help: String,
+    samples: Vec<(String, f64)>,
+}
+
+/// Renders the metrics, the energy domains are exposed as counters in joules and the
+/// other metrics as gauges.
+pub fn render(results: &HashMap<String, f64>, totals: &HashMap<String, f64>) -> String {
+    let mut families: BTreeMap<String, Family> = BTreeMap::new();
+    let mut add = |name: String, kind, help: &str, labels: &str, value| {
+        families
+            .entry(name)
+            .or_insert_with(|| Family {
+                kind,
+                help: help.to_string(),
+                samples: Vec::new(),
+            })
+            .samples
+            .push((labels.to_string(), value));
+    };
+
+    for (key, value) in results {
+        let sensor = Sensor::parse(key);
+        let labels = format_labels(&sensor.labels);
+        let help = match &sensor.unit {
+            Some(unit) => format!("{} ({})", sensor.name, unit),
+            None => sensor.name.clone(),
+        };
+        let help = help.as_str();
+        let base = metric_name(&sensor.name);
+        if is_energy(key) {
+            if let Some(total) = totals.get(key) {
+                add(format!("{}_joules_total", base), "counter", help, &labels, *total);
+            }
+        } else if let Some(factor) = power_factor(key) {
+            // the help gives the unit of the converted values
+            let watts = format!("{} (Watts)", sensor.name);
+            add(format!("{}_watts", base), "gauge", &watts, &labels, value * factor);
+            if let Some(total) = totals.get(key) {
+                let energy = sensor.name.replace("_POWER", "_ENERGY");
+                let joules = format!("{} (J)", energy);
+                let name = format!("{}_joules_total", metric_name(&energy));
+                add(name, "counter", &joules, &labels, *total);
+            }
+        } else {
+            let name = match &sensor.unit {
+                Some(unit) => format!("{}_{}", base, sanitize(unit)),
+                None => base,
+            };
+            add(name, "gauge", help, &labels, *value);
+        }
+    }
+
+    let mut page = String::new();
+    for (name, mut family) in families {
+        family.samples.sort_by(|a, b| a.0.cmp(&b.0));
+        let _ = writeln!(page, "# HELP {} EnergiBridge {}", name, family.help);
+        let _ = writeln!(page, "# TYPE {} {}", name, family.kind);
+        for (labels, value) in family.samples {
+            let _ = writeln!(page, "{}{} {}", name, labels, value);
+        }
+    }
+    page
+}
+
+fn metric_name(name: &str) -> String {
+    format!("energibridge_{}", sanitize(name))
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect()
+}
+
+fn format_labels(labels: &[(&str, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, value))
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn help_of_converted_power() {
+        let results = HashMap::from([("GPU0_POWER (mWatts)".to_string(), 120_000.0)]);
+        let totals = HashMap::from([("GPU0_POWER (mWatts)".to_string(), 360.0)]);
+        let page = render(&results, &totals);
+        assert!(page.contains("# HELP energibridge_gpu_power_watts EnergiBridge GPU_POWER (Watts)\n"));
+        assert!(page.contains("energibridge_gpu_power_watts{gpu=\"0\"} 120\n"));
+        assert!(page.contains("# HELP energibridge_gpu_energy_joules_total EnergiBridge GPU_ENERGY (J)\n"));
+        assert!(!page.contains("mWatts"));
+    }
+}
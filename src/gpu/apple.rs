@@ -3,8 +3,13 @@ use smc::SMC;
 
 use std::collections::HashMap;
 
+use crate::metrics::MetricFilter;
+
 #[cfg(target_os = "macos")]
-pub fn get_apple_gpu_counter(results: &mut HashMap<String, f64>) {
+pub fn get_apple_gpu_counter(filter: &MetricFilter, results: &mut HashMap<String, f64>) {
+    if !filter.allows("GPU_POWER (Watts)") {
+        return;
+    }
     let smc = SMC::new().unwrap();
     for key in [
         // Intel
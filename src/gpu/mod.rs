@@ -4,18 +4,36 @@ mod apple;
 mod nvidia;
 use std::collections::HashMap;
 
+use crate::metrics::MetricFilter;
+use crate::sensor::SensorInfo;
+
 #[cfg(not(target_os = "macos"))]
 use amd::get_amd_gpu_counter;
 #[cfg(target_os = "macos")]
 use apple::get_apple_gpu_counter;
 #[cfg(not(target_os = "macos"))]
-use nvidia::get_nvidia_gpu_counter;
+use nvidia::{get_nvidia_gpu_counter, probe_nvidia_sensors};
 
-pub fn get_gpu_counter(results: &mut HashMap<String, f64>) {
+pub fn get_gpu_counter(filter: &MetricFilter, results: &mut HashMap<String, f64>) {
     #[cfg(target_os = "macos")]
-    get_apple_gpu_counter(results);
+    get_apple_gpu_counter(filter, results);
     #[cfg(not(target_os = "macos"))]
-    get_nvidia_gpu_counter(results);
+    get_nvidia_gpu_counter(filter, results);
     #[cfg(not(target_os = "macos"))]
     get_amd_gpu_counter(results);
 }
+
+/// Describes the metrics of the GPUs of this machine.
+pub fn probe_gpu_sensors() -> Vec<SensorInfo> {
+    #[cfg(target_os = "macos")]
+    {
+        let mut results = HashMap::new();
+        get_apple_gpu_counter(&MetricFilter::default(), &mut results);
+        results
+            .keys()
+            .map(|name| SensorInfo::new(name, "SMC", "every sample"))
+            .collect()
+    }
+    #[cfg(not(target_os = "macos"))]
+    probe_nvidia_sensors()
+}
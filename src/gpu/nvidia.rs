@@ -1,104 +1,119 @@
 use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
-use nvml_wrapper::error::NvmlError;
-use nvml_wrapper::struct_wrappers::device::*;
 use nvml_wrapper::NVML;
+use once_cell::sync::Lazy;
 
 use std::collections::HashMap;
 
-pub struct GPUstat {
-    id: Result<u32, NvmlError>,
-    utilization_rates: Result<Utilization, NvmlError>,
-    memory_info: Result<MemoryInfo, NvmlError>,
-    // fan_speed: Result<u32, NvmlError>,
-    temperature: Result<u32, NvmlError>,
-    power: Result<u32, NvmlError>,
-}
+use crate::metrics::MetricFilter;
+use crate::sensor::{Sensor, SensorInfo};
 
-pub fn read_gpu_stat(device: &nvml_wrapper::Device) -> GPUstat {
-    let gpustat = GPUstat {
-        id: device.index(),
-        utilization_rates: device.utilization_rates(),
-        memory_info: device.memory_info(),
-        // fan_speed: device.fan_speed(0), // Currently only take one fan, will add more fan readings
-        temperature: device.temperature(TemperatureSensor::Gpu),
-        power: device.power_usage(),
-    };
+// NVML is loaded once, initializing it takes longer than reading all the other sensors
+static NVML_INSTANCE: Lazy<Option<NVML>> = Lazy::new(|| NVML::init().ok());
+
+// Number of GPUs checked against the filter before loading NVML
+const MAX_PROBED_GPUS: u32 = 16;
 
-    return gpustat;
+// Column names of the metrics of a GPU, in the order they are read.
+fn gpu_metric_names(index: u32) -> [String; 5] {
+    [
+        format!("GPU{}_USAGE", index),
+        format!("GPU{}_MEMORY_USED", index),
+        format!("GPU{}_MEMORY_TOTAL", index),
+        format!("GPU{}_TEMPERATURE", index),
+        format!("GPU{}_POWER (mWatts)", index),
+    ]
 }
 
-pub fn dump_gpu_stat(device: nvml_wrapper::Device, results: &mut HashMap<String, f64>) {
-    let gpustat = read_gpu_stat(&device);
-    let index = gpustat.id.unwrap();
+pub fn dump_gpu_stat(
+    device: nvml_wrapper::Device,
+    index: u32,
+    filter: &MetricFilter,
+    results: &mut HashMap<String, f64>,
+) {
+    let [usage_key, memory_used_key, memory_total_key, temperature_key, power_key] =
+        gpu_metric_names(index);
 
-    match gpustat.utilization_rates {
-        Ok(utilization_rates) => {
-            results.insert(
-                format!("GPU{}_USAGE", index).to_string(),
-                utilization_rates.gpu.into(),
-            );
+    // only query the device for the selected metrics
+    if filter.allows(&usage_key) {
+        if let Ok(utilization_rates) = device.utilization_rates() {
+            results.insert(usage_key, utilization_rates.gpu.into());
         }
-        Err(_) => {}
-    };
+    }
 
-    match gpustat.memory_info {
-        Ok(memory_info) => {
-            results.insert(
-                format!("GPU{}_MEMORY_USED", index).to_string(),
-                (memory_info.used / 1024 / 1024) as f64,
-            );
-            results.insert(
-                format!("GPU{}_MEMORY_TOTAL", index).to_string(),
-                (memory_info.total / 1024 / 1024) as f64,
-            );
+    if filter.allows_any(&[&memory_used_key, &memory_total_key]) {
+        if let Ok(memory_info) = device.memory_info() {
+            results.insert(memory_used_key, (memory_info.used / 1024 / 1024) as f64);
+            results.insert(memory_total_key, (memory_info.total / 1024 / 1024) as f64);
         }
-        Err(_) => {}
-    };
+    }
+
+    // fan_speed: device.fan_speed(0), // Currently only take one fan, will add more fan readings
 
-    // let fan_speed = match gpustat.fan_speed {
-    //     Ok(fan_speed) => format!("{:>3} % | ", fan_speed),
-    //     Err(_err) => "".to_string(),
-    // };
-    // result.push_str(&fan_speed);
-
-    match gpustat.temperature {
-        Ok(temperature) => {
-            results.insert(
-                format!("GPU{}_TEMPERATURE", index).to_string(),
-                temperature.into(),
-            );
+    if filter.allows(&temperature_key) {
+        if let Ok(temperature) = device.temperature(TemperatureSensor::Gpu) {
+            results.insert(temperature_key, temperature.into());
         }
-        Err(_) => {}
-    };
+    }
 
-    match gpustat.power {
-        Ok(power) => {
-            let key = format!("GPU{}_POWER (mWatts)", index).to_string();
-            results.insert(key, power.into());
+    if filter.allows(&power_key) {
+        if let Ok(power) = device.power_usage() {
+            results.insert(power_key, power.into());
         }
-        Err(_) => {}
-    };
+    }
 }
 
 pub fn dump_all_gpu_stats(
     nvml: &nvml_wrapper::NVML,
+    filter: &MetricFilter,
     results: &mut HashMap<String, f64>,
 ) -> Result<(), nvml_wrapper::error::NvmlErrorWithSource> {
     let device_count = nvml.device_count()?;
 
     for i in 0..device_count {
+        if !filter.allows_any(&gpu_metric_names(i)) {
+            continue;
+        }
         let device = nvml.device_by_index(i)?;
-        dump_gpu_stat(device, results);
+        dump_gpu_stat(device, i, filter, results);
     }
 
-    return Ok(());
+    Ok(())
 }
 
-pub fn get_nvidia_gpu_counter(results: &mut HashMap<String, f64>) {
-    match NVML::init() {
-        Ok(nvml) => dump_all_gpu_stats(&nvml, results).unwrap(),
-        Err(_) => {
+pub fn get_nvidia_gpu_counter(filter: &MetricFilter, results: &mut HashMap<String, f64>) {
+    // avoid loading NVML if no GPU metric can be selected
+    let names: Vec<String> = (0..MAX_PROBED_GPUS).flat_map(gpu_metric_names).collect();
+    if !filter.allows_any(&names) {
+        return;
+    }
+    match NVML_INSTANCE.as_ref() {
+        Some(nvml) => dump_all_gpu_stats(nvml, filter, results).unwrap(),
+        None => {
             // nvml not available
         }
     }
 }
+
+/// Describes the metrics of the GPUs found by NVML.
+pub fn probe_nvidia_sensors() -> Vec<SensorInfo> {
+    let Some(nvml) = NVML_INSTANCE.as_ref() else {
+        return Vec::new();
+    };
+    let mut results = HashMap::new();
+    // a GPU failing to answer only hides its metrics and the following ones
+    let _ = dump_all_gpu_stats(nvml, &MetricFilter::default(), &mut results);
+    let mut names: Vec<_> = results.into_keys().collect();
+    names.sort();
+    names
+        .iter()
+        .map(|name| {
+            // NVML averages the utilization and the power over its sampling period
+            let update_rate = match Sensor::parse(name).name.as_str() {
+                "GPU_USAGE" => "1/6 s to 1 s",
+                "GPU_POWER" => "~1 s",
+                _ => "every sample",
+            };
+            SensorInfo::new(name, "NVML", update_rate)
+        })
+        .collect()
+}
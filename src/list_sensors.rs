@@ -0,0 +1,203 @@
+use clap::Args;
+use sysinfo::{System, SystemExt};
+
+use crate::cpu::{cpu_usage_names, probe_cpu_counter};
+use crate::gpu::probe_gpu_sensors;
+use crate::memory::MEMORY_METRICS;
+use crate::metrics::CpuDetail;
+use crate::sensor::SensorInfo;
+
+/// Options of the `list-sensors` subcommand.
+#[derive(Args, Debug)]
+pub struct ListSensorsArgs {
+    /// Print the sensors as JSON instead of a table
+    #[arg(long, default_value_t = false)]
+    pub json: bool,
+
+    /// List the CPU usage and frequency per core or aggregated over all the cores
+    #[arg(long, value_enum, default_value_t = CpuDetail::PerCore)]
+    pub cpu_detail: CpuDetail,
+}
+
+/// Probes every backend and prints the metrics that can be measured on this machine.
+pub fn run(args: &ListSensorsArgs) {
+    #[cfg(not(target_os = "macos"))]
+    crate::cpu::msr::start_rapl();
+    let mut sys = System::new_all();
+
+    let mut sensors = probe_cpu_counter(&mut sys);
+    sensors.extend(probe_gpu_sensors());
+    for name in cpu_usage_names(&sys, args.cpu_detail) {
+        sensors.push(SensorInfo::new(&name, "sysinfo", "every sample"));
+    }
+    for name in MEMORY_METRICS {
+        sensors.push(SensorInfo::new(name, "sysinfo", "every sample"));
+    }
+    #[cfg(target_os = "linux")]
+    sensors.extend(sysfs::probe(std::path::Path::new(sysfs::SYSFS)));
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&sensors).expect("Failed to serialize the sensors")
+        );
+    } else {
+        print_table(&sensors);
+    }
+}
+
+fn print_table(sensors: &[SensorInfo]) {
+    let rows: Vec<[String; 7]> = sensors
+        .iter()
+        .map(|sensor| {
+            let yes_no = |value: bool| if value { "yes" } else { "no" }.to_string();
+            [
+                sensor.metric.clone(),
+                sensor.unit.clone().unwrap_or_else(|| "-".to_string()),
+                sensor.source.clone(),
+                sensor.update_rate.to_string(),
+                sensor.privileges.unwrap_or("-").to_string(),
+                yes_no(sensor.readable),
+                yes_no(sensor.recorded),
+            ]
+        })
+        .collect();
+    let header = ["METRIC", "UNIT", "SOURCE", "UPDATE RATE", "PRIVILEGES", "READABLE", "RECORDED"]
+        .map(String::from);
+    let mut widths = [0; 7];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+// The sensors exposed by the kernel that EnergiBridge does not record.
+#[cfg(target_os = "linux")]
+mod sysfs {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    use crate::sensor::SensorInfo;
+
+    pub const SYSFS: &str = "/sys/class";
+
+    // Prefixes of the hwmon attributes with the unit of their value.
+    const HWMON_UNITS: [(&str, &str); 6] = [
+        ("temp", "m°C"),
+        ("power", "µW"),
+        ("energy", "µJ"),
+        ("in", "mV"),
+        ("curr", "mA"),
+        ("fan", "RPM"),
+    ];
+
+    // Attributes of a battery with the unit of their value.
+    const BATTERY_ATTRIBUTES: [(&str, &str); 4] = [
+        ("power_now", "µW"),
+        ("energy_now", "µWh"),
+        ("current_now", "µA"),
+        ("voltage_now", "µV"),
+    ];
+
+    pub fn probe(root: &Path) -> Vec<SensorInfo> {
+        let mut sensors = Vec::new();
+        for dir in entries(&root.join("powercap")) {
+            let path = dir.join("energy_uj");
+            if !path.exists() {
+                continue;
+            }
+            // the energy counters are only readable by root since Linux 5.10
+            let name = format!("{} {}", file_name(&dir), read(&dir.join("name")));
+            sensors.push(sensor(name, "µJ", &path, "~1 ms"));
+        }
+
+        for dir in entries(&root.join("hwmon")) {
+            let device = read(&dir.join("name"));
+            for path in entries(&dir) {
+                let file = file_name(&path);
+                let Some(channel) = file
+                    .strip_suffix("_input")
+                    .or_else(|| file.strip_suffix("_average"))
+                else {
+                    continue;
+                };
+                let Some((_, unit)) = HWMON_UNITS.iter().find(|(prefix, _)| {
+                    channel
+                        .strip_prefix(prefix)
+                        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+                }) else {
+                    continue;
+                };
+                let label = read(&dir.join(format!("{}_label", channel)));
+                let label = if label.is_empty() { channel.to_string() } else { label };
+                let name = format!("{} {}", device, label);
+                sensors.push(sensor(name, unit, &path, "driver dependent"));
+            }
+        }
+
+        for dir in entries(&root.join("thermal")) {
+            let path = dir.join("temp");
+            if file_name(&dir).starts_with("thermal_zone") && path.exists() {
+                let name = format!("{} {}", file_name(&dir), read(&dir.join("type")));
+                sensors.push(sensor(name, "m°C", &path, "driver dependent"));
+            }
+        }
+
+        for dir in entries(&root.join("power_supply")) {
+            if read(&dir.join("type")) != "Battery" {
+                continue;
+            }
+            for (attribute, unit) in BATTERY_ATTRIBUTES {
+                let path = dir.join(attribute);
+                if path.exists() {
+                    let name = format!("{} {}", file_name(&dir), attribute);
+                    sensors.push(sensor(name, unit, &path, "a few seconds"));
+                }
+            }
+        }
+        sensors
+    }
+
+    fn sensor(name: String, unit: &str, path: &Path, update_rate: &'static str) -> SensorInfo {
+        // the files that are not readable by everyone are reserved to root
+        let public = fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o004 != 0);
+        SensorInfo {
+            metric: name.trim().to_string(),
+            unit: Some(unit.to_string()),
+            source: path.display().to_string(),
+            update_rate,
+            privileges: (!public).then_some("root"),
+            readable: fs::read_to_string(path).is_ok(),
+            recorded: false,
+        }
+    }
+
+    // The entries of a directory, sorted by name.
+    fn entries(dir: &Path) -> Vec<PathBuf> {
+        let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_default();
+        entries.sort();
+        entries
+    }
+
+    fn file_name(path: &Path) -> String {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap_or_default().trim().to_string()
+    }
+}
@@ -1,29 +1,149 @@
+mod affinity;
+mod analyze;
+mod config;
+mod control;
 mod cpu;
+mod doctor;
+mod energy;
+mod exporter;
 mod gpu;
+mod list_sensors;
 mod memory;
-mod process;
+mod metadata;
+mod metrics;
+mod overhead;
+mod phase;
+mod plot;
+mod sensor;
+mod simulate;
+mod sink;
+mod summary;
+mod wraparound;
 
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
 
-use itertools::Itertools;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{stdout, Write};
+use std::io::stdout;
+use std::path::{Path, PathBuf};
 use std::process::{exit, Child};
 use std::process::{Command, Stdio};
 use std::thread::sleep;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use sysinfo::{CpuExt, ProcessExt, RefreshKind, System, SystemExt};
+use sysinfo::{System, SystemExt};
 
+use affinity::{configure_sampler, ChildScheduling, CpuList};
+use analyze::AnalyzeArgs;
+use control::{ControlSocket, ControlState};
 use cpu::{get_cpu_counter, get_cpu_usage};
+use energy::{primary_domain, EnergyAccumulator};
+use exporter::Exporter;
 use gpu::get_gpu_counter;
+use list_sensors::ListSensorsArgs;
 use memory::get_memory_usage;
+use metadata::Metadata;
+use metrics::{CpuDetail, MetricFilter};
+use overhead::Overhead;
+use phase::output::{OutputWatcher, PhasePatterns};
+use phase::PhaseTracker;
+use sink::csv::CsvSink;
+use sink::file::{create_output, Output, RotatingSink, Rotation};
+use sink::influx::InfluxSink;
+use sink::jsonl::JsonlSink;
+use sink::long::LongSink;
+use sink::otlp::OtlpSink;
+use sink::ipc::IpcSink;
+use sink::parquet::ParquetSink;
+use sink::plot::PlotSink;
+use sink::report::ReportSink;
+use sink::{OutputFormat, Sample, Sink};
+use summary::{print_phases, Summary};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+
+    // options of `run`, which is the default mode
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Mode {
+    /// Run a command and measure the energy consumed during its execution (default)
+    Run(Box<RunArgs>),
+    /// Serve the metrics to Prometheus on /metrics until EnergiBridge is stopped
+    Monitor(MonitorArgs),
+    /// Print the energy consumption recorded in a CSV output of EnergiBridge
+    Analyze(AnalyzeArgs),
+    /// List the metrics that can be measured on this machine
+    ListSensors(ListSensorsArgs),
+    /// Check the permissions and the setup needed to read the sensors
+    Doctor,
+}
+
+// Options selecting the metrics and how often they are sampled.
+#[derive(clap::Args, Debug, Serialize, Deserialize)]
+struct SamplingArgs {
+    /// Duration of the interval between two measurements in micoseconds
+    #[arg(short, long, default_value_t = 200)]
+    interval: u32,
+
+    // enable to measure the GPU power consumption
+    #[arg(short, long, default_value_t = false)]
+    gpu: bool,
+
+    /// Only collect the metrics matching one of these glob patterns, e.g. "*_ENERGY*,GPU*_POWER*"
+    #[arg(long, value_delimiter = ',')]
+    metrics: Vec<String>,
+
+    /// Do not collect the metrics matching one of these glob patterns
+    #[arg(long, value_delimiter = ',')]
+    exclude: Vec<String>,
+
+    /// Report the CPU usage and frequency per core or aggregated over all the cores
+    #[arg(long, value_enum, default_value_t = CpuDetail::PerCore)]
+    cpu_detail: CpuDetail,
+
+    /// Replace the sensors with the synthetic hardware described by this TOML scenario
+    #[arg(long)]
+    simulate: Option<String>,
+}
+
+impl SamplingArgs {
+    fn filter(&self) -> MetricFilter {
+        MetricFilter::new(self.metrics.clone(), self.exclude.clone(), self.cpu_detail)
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct MonitorArgs {
+    /// Port (or address:port) on which the metrics are served
+    #[arg(short, long, default_value = "9100")]
+    listen: String,
+
+    #[command(flatten)]
+    sampling: SamplingArgs,
+}
+
+#[derive(clap::Args, Debug, Serialize, Deserialize)]
+struct RunArgs {
+    /// Configuration file to load, defaults to energibridge.toml in the working directory
+    #[arg(long)]
+    #[serde(skip)]
+    config: Option<String>,
+
+    /// Profile of the configuration file to apply
+    #[arg(long)]
+    #[serde(skip)]
+    profile: Option<String>,
+
     // Where to save the output of power measurements
     #[arg(short, long)]
     output: Option<String>,
@@ -31,26 +151,106 @@ struct Args {
     #[arg(short, long, default_value = ",")]
     separator: String,
 
+    /// Format of the measurements
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    format: OutputFormat,
+
+    /// Start a new output file every period (e.g. 1h) or size (e.g. 100MB), the
+    /// output is compressed if its name ends with .gz or .zst
+    #[arg(long)]
+    rotate: Option<Rotation>,
+
+    /// Number of samples written together in a row group of the Parquet output or a
+    /// record batch of the Arrow output
+    #[arg(long, default_value_t = 300)]
+    row_group_size: usize,
+
     // Where to save the output of the command
     #[arg(short, long, required = false)]
     command_output: Option<String>,
 
-    /// Duration of the interval between two measurements in micoseconds
-    #[arg(short, long, default_value_t = 200)]
-    interval: u32,
-
     /// Define the maximum duration of the execution of the command in seconds, set to 0 to disable
     #[arg(short, long, default_value_t = 0)]
     max_execution: u32,
 
-    // enable to measure the GPU power consumption
-    #[arg(short, long, default_value_t = false)]
-    gpu: bool,
-
     // print the summary of the energy consumption
     #[arg(long, default_value_t = false)]
     summary: bool,
 
+    /// Also print the energy and the power of every domain in the summary
+    #[arg(long, default_value_t = false)]
+    summary_domains: bool,
+
+    /// Report the CPU time and the estimated energy consumed by EnergiBridge itself
+    #[arg(long, default_value_t = false)]
+    overhead: bool,
+
+    /// Where to save the metadata of the run as JSON
+    #[arg(long)]
+    metadata: Option<String>,
+
+    /// Log every MSR read during the run to this file, to replay it with --replay-msr
+    #[arg(long, conflicts_with_all = ["replay_msr", "simulate"])]
+    record_msr: Option<String>,
+
+    /// Read the MSRs from a file recorded with --record-msr instead of the CPU
+    #[arg(long, conflicts_with = "simulate")]
+    replay_msr: Option<String>,
+
+    /// Pin EnergiBridge's sampling thread to this CPU (Linux only)
+    #[arg(long)]
+    sampler_cpu: Option<usize>,
+
+    /// Run the sampling thread with this real-time (SCHED_FIFO) priority (Linux only)
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..=99))]
+    sampler_priority: Option<i32>,
+
+    /// CPUs the command is allowed to run on, e.g. 2-7 or 0,2,4 (Linux only)
+    #[arg(long)]
+    cpus: Option<CpuList>,
+
+    /// Nice level of the command (Linux only)
+    #[arg(long, allow_negative_numbers = true, value_parser = clap::value_parser!(i32).range(-20..=19))]
+    nice: Option<i32>,
+
+    /// Create a channel where the command writes `begin <name>`/`end <name>` phase markers,
+    /// its path is given to the command in the ENERGIBRIDGE_MARKER environment variable
+    #[arg(long, default_value_t = false)]
+    markers: bool,
+
+    /// Open a phase when a line of the command's output matches this regex, the phase is
+    /// named after the first capture group and ends when another phase begins
+    #[arg(long)]
+    phase_regex: Vec<String>,
+
+    /// Close the current phase when a line of the command's output matches this regex
+    #[arg(long)]
+    phase_end_regex: Vec<String>,
+
+    /// Unix socket accepting start-window, stop-window, mark <label>, snapshot and stop commands
+    #[arg(long)]
+    control_socket: Option<String>,
+
+    /// Push the metrics to this OTLP/HTTP endpoint, e.g. http://localhost:4318/v1/metrics
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+
+    /// Number of samples sent together to the OTLP endpoint
+    #[arg(long, default_value_t = 10)]
+    otlp_batch: usize,
+
+    /// Draw the power, usage and temperature over time to this SVG or PNG file
+    #[arg(long)]
+    plot: Option<String>,
+
+    /// Write a self-contained HTML report of the run to this file
+    #[arg(long)]
+    report: Option<String>,
+
+    #[command(flatten)]
+    #[serde(flatten)]
+    sampling: SamplingArgs,
+
     // the command to execute
     #[clap(trailing_var_arg = true)]
     command: Vec<String>,
@@ -58,59 +258,200 @@ struct Args {
 
 fn main() {
     // EXAMPLE https://gist.github.com/carstein/6f4a4fdf04ec002d5494a11d2cf525c7
-    let args = Args::parse();
-    let interval = Duration::from_millis(args.interval.into());
-    let sep = args.separator.as_str();
-    let collect_gpu = args.gpu;
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    match cli.mode {
+        None => run(cli.run, &matches),
+        Some(Mode::Run(args)) => run(*args, matches.subcommand_matches("run").unwrap()),
+        Some(Mode::Monitor(args)) => monitor(args),
+        Some(Mode::Analyze(args)) => {
+            if let Err(e) = analyze::run(&args) {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Some(Mode::ListSensors(args)) => list_sensors::run(&args),
+        Some(Mode::Doctor) => {
+            if !doctor::run() {
+                exit(1);
+            }
+        }
+    }
+}
+
+/// Starts the energy counters, or the simulation of `sampling`, and takes the first
+/// sample of the system.
+///
+/// Returns the flag cleared when EnergiBridge receives Ctrl+C.
+fn start_sampling(sampling: &SamplingArgs) -> (System, Arc<AtomicBool>) {
+    let interval = Duration::from_millis(sampling.interval.into());
     // Create an atomic flag to indicate when to stop the execution loop
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
 
-    if args.command.is_empty() {
-        eprintln!("Usage: {} <command>", "EnergiBridge");
-        exit(1);
-    }
-
     if interval < System::MINIMUM_CPU_UPDATE_INTERVAL {
         eprintln!(
             "[WARNING] Interval must be at least {}ms to accurately measure CPU usage.",
             System::MINIMUM_CPU_UPDATE_INTERVAL.as_millis()
         );
     }
-    
+
     // Set up the Ctrl+C handler
     ctrlc::set_handler(move || {
         println!("\nReceived Ctrl+C, stopping...");
         r.store(false, Ordering::SeqCst);
     }).expect("Error setting Ctrl-C handler");
 
-    #[cfg(not(target_os = "macos"))]
-    cpu::msr::start_rapl();
+    if let Some(path) = &sampling.simulate {
+        simulate::start(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Invalid simulation scenario: {}", e);
+            exit(1);
+        });
+    } else {
+        #[cfg(not(target_os = "macos"))]
+        cpu::msr::start_rapl();
+    }
 
     let mut sys = System::new_all();
     sys.refresh_all();
     std::thread::sleep(System::MINIMUM_CPU_UPDATE_INTERVAL);
+    (sys, running)
+}
+
+/// Runs a command and measures the energy consumed during its execution.
+fn run(mut args: RunArgs, matches: &ArgMatches) {
+    let config_file = config::config_path(args.config.as_deref());
+    if let Some(path) = &config_file {
+        let (config, profile) = (args.config.take(), args.profile.take());
+        args = config::apply_config(args, matches, path, profile.as_deref()).unwrap_or_else(|e| {
+            eprintln!("Invalid configuration: {}", e);
+            exit(1);
+        });
+        (args.config, args.profile) = (config, profile);
+    } else if let Some(profile) = &args.profile {
+        eprintln!("Profile `{}` requires a configuration file", profile);
+        exit(1);
+    }
+    if args.command.is_empty() {
+        eprintln!("Usage: EnergiBridge <command>");
+        exit(1);
+    }
+
+    let interval = Duration::from_millis(args.sampling.interval.into());
+    let collect_gpu = args.sampling.gpu;
+    let filter = args.sampling.filter();
+    let (mut sys, running) = start_sampling(&args.sampling);
+    #[cfg(not(target_os = "macos"))]
+    set_msr_source(&args, &sys);
     let mut results: HashMap<String, f64> = HashMap::new();
-    collect(&mut sys, collect_gpu, 0, &mut results);
+    let mut overhead = args.overhead.then(Overhead::new);
+    collect(&mut sys, collect_gpu, &filter, args.overhead, &mut results);
+    if let Some(overhead) = overhead.as_mut() {
+        overhead.update(&sys, Duration::ZERO, None, &filter, &mut results);
+    }
 
-    let mut output = match args.output {
-        Some(ref path) => {
-            Box::new(File::create(path).expect("Failed to open output file")) as Box<dyn Write>
-        }
-        None => Box::new(stdout()) as Box<dyn Write>,
+    let mut metadata = Metadata::new(&args.command);
+    metadata.insert("config", &args);
+    metadata.insert("config_file", &config_file);
+    metadata.insert("profile", &args.profile);
+    #[cfg(not(target_os = "macos"))]
+    {
+        let cpu = cpu::cpu_id(&sys);
+        metadata.insert("cpu_microarchitecture", cpu::model::microarchitecture(&cpu).map(|arch| arch.name));
+        metadata.insert("cpu", cpu);
+    }
+    let scheduling = ChildScheduling {
+        cpus: args.cpus.clone(),
+        nice: args.nice,
+    };
+
+    let mut phases = PhaseTracker::new();
+    let patterns = PhasePatterns::new(&args.phase_regex, &args.phase_end_regex)
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid phase regex: {}", e);
+            exit(1);
+        });
+    let mut watcher =
+        (!patterns.is_empty()).then(|| OutputWatcher::new(patterns, phases.sender()));
+    let control = args.control_socket.as_ref().map(|path| {
+        ControlSocket::bind(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Failed to create the control socket: {}", e);
+            exit(1);
+        })
+    });
+    // the marks of the control socket are only reported in the PHASE column if another
+    // source of phases enables it, the header is written before any mark can be received
+    let record_phases = args.markers || watcher.is_some();
+
+    let mut sinks = vec![output_sink(&args, record_phases, metadata.run_id())];
+    if let Some(endpoint) = &args.otlp_endpoint {
+        let attributes = vec![
+            ("host.name", sys.host_name().unwrap_or_default()),
+            ("energibridge.run_id", metadata.run_id().to_string()),
+            ("process.command_line", args.command.join(" ")),
+        ];
+        sinks.push(Box::new(OtlpSink::new(
+            endpoint,
+            args.otlp_batch,
+            attributes,
+            SystemTime::now(),
+        )));
+    }
+    if let Some(path) = &args.plot {
+        sinks.push(Box::new(PlotSink::new(path)));
+    }
+    if let Some(path) = &args.report {
+        sinks.push(Box::new(ReportSink::new(path, record_phases)));
+    }
+    let mut env = Vec::new();
+    #[cfg(unix)]
+    let marker_channel = if args.markers {
+        let channel = phase::marker::MarkerChannel::open(phases.sender())
+            .expect("Failed to create the marker channel");
+        env.push((phase::marker::MARKER_ENV, channel.path().to_path_buf()));
+        Some(channel)
+    } else {
+        None
     };
+    #[cfg(not(unix))]
+    if args.markers {
+        eprintln!("[WARNING] Phase markers are only supported on Unix systems.");
+    }
 
-    let cmd = execute_command(args.command, args.command_output);
+    let cmd = execute_command(
+        args.command,
+        args.command_output,
+        &scheduling,
+        &env,
+        watcher.as_mut(),
+    );
 
     match cmd {
         Ok(mut child) => {
             let start_time = Instant::now();
 
-            collect(&mut sys, collect_gpu, child.id(), &mut results);
-            print_header(&results, sep, &mut output);
+            // configured once the command is started so that it does not inherit the settings
+            match configure_sampler(args.sampler_cpu, args.sampler_priority) {
+                Ok(()) => {
+                    metadata.insert("sampler_cpu", args.sampler_cpu);
+                    metadata.insert("sampler_priority", args.sampler_priority);
+                }
+                Err(e) => eprintln!("[WARNING] Failed to configure the sampler: {}", e),
+            }
+
+            collect(&mut sys, collect_gpu, &filter, args.overhead, &mut results);
+            // the overhead columns are added to the results after each collect
+            if let Some(overhead) = overhead.as_mut() {
+                overhead.update(&sys, Duration::ZERO, None, &filter, &mut results);
+            }
+            for sink in sinks.iter_mut() {
+                sink.header(&results);
+            }
             let mut previous_time = SystemTime::now();
-            let mut energy_array: f64 = 0 as f64;
-            let mut previous_results = results.clone();
+            let mut accumulator = EnergyAccumulator::new();
+            accumulator.update(&results, Duration::ZERO);
+            let mut summary = Summary::new();
+            let mut control_state = ControlState::default();
             let exit_code = loop {
                 if args.max_execution > 0
                     && start_time.elapsed().as_secs() >= args.max_execution as u64
@@ -120,67 +461,222 @@ fn main() {
                     break 0;
                 }
                 let time_before = SystemTime::now();
-                print_results(previous_time, &mut results, sep, &mut output);
-
-                if args.summary {
-                    if results.contains_key("CPU_POWER (Watts)") {
-                        let energy = results["CPU_POWER (Watts)"];
-                        energy_array += energy
-                            * (previous_time.elapsed().unwrap().as_millis() as f64 / 1000 as f64);
-                    } else if results.contains_key("SYSTEM_POWER (Watts)") {
-                        let energy = results["SYSTEM_POWER (Watts)"];
-                        energy_array += energy
-                            * (previous_time.elapsed().unwrap().as_millis() as f64 / 1000 as f64);
-                    } else if results.contains_key("CPU_ENERGY (J)") {
-                        let energy = results["CPU_ENERGY (J)"];
-                        let old_energy = previous_results["CPU_ENERGY (J)"];
-                        energy_array += energy - old_energy;
-                    } else if results.contains_key("PACKAGE_ENERGY (J)") {
-                        let energy = results["PACKAGE_ENERGY (J)"];
-                        let old_energy = previous_results["PACKAGE_ENERGY (J)"];
-                        energy_array += energy - old_energy;
+                let phase = record_phases.then(|| phases.label());
+                let sample = Sample {
+                    delta: previous_time.elapsed().unwrap(),
+                    time: previous_time,
+                    results: &results,
+                    energy: accumulator.totals(),
+                    phase: phase.as_deref(),
+                };
+                sinks.iter_mut().for_each(|sink| sink.write(&sample));
+
+                let elapsed = previous_time.elapsed().unwrap();
+                previous_time = SystemTime::now();
+                let sample_end = Instant::now();
+                collect(&mut sys, collect_gpu, &filter, args.overhead, &mut results);
+                let deltas = accumulator.update(&results, elapsed);
+                summary.update(&deltas, elapsed);
+                let cpu_energy = primary_domain(&results)
+                    .and_then(|key| deltas.get(key))
+                    .copied();
+                if let Some(overhead) = overhead.as_mut() {
+                    overhead.update(&sys, elapsed, cpu_energy, &filter, &mut results);
+                }
+                if let Some(control) = control.as_ref() {
+                    while let Some(request) = control.try_recv() {
+                        control_state.handle(request, &accumulator, start_time, &phases);
                     }
                 }
-                previous_time = SystemTime::now();
-                previous_results = results.clone();
-                collect(&mut sys, collect_gpu, child.id(), &mut results);
+                phases.update(sample_end, elapsed, cpu_energy);
 
                 if !running.load(Ordering::SeqCst) {
                     // EnergiBridge received ctrlc
                     child.kill().expect("Failed to kill child");
                     break 1;
                 }
+                if control_state.stop_requested() {
+                    child.kill().expect("Failed to kill child");
+                    break 0;
+                }
                 match child.try_wait() {
                     Ok(Some(status)) => {
                         // print_results(previous_time, &mut results, sep, &mut output);
+                        if let Some(watcher) = watcher.as_mut() {
+                            // apply the phases of the last lines of output
+                            watcher.finish();
+                            phases.update(Instant::now(), Duration::ZERO, None);
+                        }
                         break status.code().unwrap();
                     }
                     Ok(None) => {
-                        sleep(interval - time_before.elapsed().unwrap());
+                        sleep(interval.saturating_sub(time_before.elapsed().unwrap()));
                     }
                     Err(e) => println!("Error waiting: {}", e),
                 }
             };
 
-            print_results(previous_time, &mut results, sep, &mut output);
-            if energy_array > 0.0 && args.summary {
-                println!(
-                    "Energy consumption in joules: {} for {} sec of execution.",
-                    energy_array,
-                    start_time.elapsed().as_secs_f32()
+            let phase = record_phases.then(|| phases.label());
+            let sample = Sample {
+                delta: previous_time.elapsed().unwrap(),
+                time: previous_time,
+                results: &results,
+                energy: accumulator.totals(),
+                phase: phase.as_deref(),
+            };
+            metadata.insert("exit_code", exit_code);
+            metadata.insert("duration_ms", start_time.elapsed().as_millis() as u64);
+            // lets analyze detect the wraps of the counters of this run
+            metadata.insert("counter_ranges", wraparound::counter_ranges());
+            for sink in sinks.iter_mut() {
+                sink.write(&sample);
+                sink.metadata(&metadata);
+                sink.finish();
+            }
+            // the compressed outputs are completed when they are dropped
+            drop(sinks);
+            #[cfg(not(target_os = "macos"))]
+            if let Err(e) = cpu::msr::flush_msr_reader() {
+                eprintln!("[WARNING] Failed to write the MSR recording: {}", e);
+            }
+            if args.summary || args.summary_domains {
+                summary.print(
+                    primary_domain(&results),
+                    start_time.elapsed(),
+                    args.summary_domains,
                 );
+                if let Some(overhead) = overhead.as_ref() {
+                    println!(
+                        "EnergiBridge overhead: {} joules for {} sec of CPU time.",
+                        overhead.energy(),
+                        overhead.cpu_time().as_secs_f32()
+                    );
+                }
+                print_phases(phases.stats());
+            }
+
+            if let Some(path) = args.metadata {
+                metadata
+                    .write(&path)
+                    .expect("Failed to write metadata file");
             }
 
+            #[cfg(unix)]
+            drop(marker_channel);
+            drop(control);
             exit(exit_code);
         }
         Err(err) => {
             eprintln!("Failed to execute command: {}", err);
+            // exit does not run the destructors removing the pipe and the socket
+            #[cfg(unix)]
+            drop(marker_channel);
+            drop(control);
             exit(1);
         }
     }
 }
 
-fn execute_command(command: Vec<String>, output: Option<String>) -> std::io::Result<Child> {
+/// Replaces the reader of the MSRs to record or replay the registers read during the run.
+#[cfg(not(target_os = "macos"))]
+fn set_msr_source(args: &RunArgs, sys: &System) {
+    use cpu::msr::record::{MsrRecorder, MsrReplay};
+    use cpu::msr::{set_msr_reader, system_reader};
+
+    if let Some(path) = &args.replay_msr {
+        let replay = MsrReplay::open(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Failed to read the MSR recording {}: {}", path, e);
+            exit(1);
+        });
+        set_msr_reader(Box::new(replay));
+    } else if let Some(path) = &args.record_msr {
+        let recorder = MsrRecorder::create(Path::new(path), system_reader(), &cpu::cpu_id(sys))
+            .expect("Failed to create the MSR recording");
+        set_msr_reader(Box::new(recorder));
+    }
+}
+
+/// Creates the sink writing the measurements to `output`, or to stdout if not set.
+fn output_sink(args: &RunArgs, phase: bool, run_id: &str) -> Box<dyn Sink> {
+    let output = args.output.as_deref();
+    if let Some(url) = output.filter(|o| o.starts_with("http://") || o.starts_with("https://")) {
+        if args.format != OutputFormat::Influx {
+            eprintln!("Only the influx format can be sent to an HTTP endpoint");
+            exit(1);
+        }
+        return Box::new(InfluxSink::http(url, run_id));
+    }
+    let binary = matches!(args.format, OutputFormat::Parquet | OutputFormat::Arrow);
+    if output.is_none() && (binary || args.rotate.is_some()) {
+        eprintln!("The parquet and arrow formats and the rotation require an output file");
+        exit(1);
+    }
+
+    let (format, sep, row_group_size) = (args.format, args.separator.clone(), args.row_group_size);
+    let run_id = run_id.to_string();
+    let open = move |output: Output| -> Box<dyn Sink> {
+        match format {
+            OutputFormat::Csv => Box::new(CsvSink::new(output, &sep, phase)),
+            OutputFormat::Influx => Box::new(InfluxSink::new(output, &run_id)),
+            OutputFormat::Jsonl => Box::new(JsonlSink::new(output)),
+            OutputFormat::Long => Box::new(LongSink::new(output, &sep, phase)),
+            OutputFormat::Parquet => {
+                Box::new(ParquetSink::new(output, &run_id, phase, row_group_size))
+            }
+            OutputFormat::Arrow => Box::new(IpcSink::new(output, &run_id, phase, row_group_size)),
+        }
+    };
+    match (output, args.rotate) {
+        (Some(path), Some(rotation)) => Box::new(
+            RotatingSink::new(Path::new(path), rotation, Box::new(open))
+                .expect("Failed to open output file"),
+        ),
+        (Some(path), None) => open(
+            create_output(Path::new(path), Default::default()).expect("Failed to open output file"),
+        ),
+        (None, _) => open(Box::new(stdout())),
+    }
+}
+
+/// Samples the metrics until EnergiBridge is stopped and serves them to Prometheus.
+fn monitor(args: MonitorArgs) {
+    // a port alone is served on the loopback interface
+    let address = match args.listen.parse::<u16>() {
+        Ok(port) => format!("127.0.0.1:{}", port),
+        Err(_) => args.listen.clone(),
+    };
+    let exporter = Exporter::start(&address).unwrap_or_else(|e| {
+        eprintln!("Failed to start the exporter on {}: {}", address, e);
+        exit(1);
+    });
+    eprintln!("Serving the metrics on http://{}/metrics", address);
+
+    let interval = Duration::from_millis(args.sampling.interval.into());
+    let filter = args.sampling.filter();
+    let (mut sys, running) = start_sampling(&args.sampling);
+    let mut results = HashMap::new();
+    collect(&mut sys, args.sampling.gpu, &filter, false, &mut results);
+    let mut accumulator = EnergyAccumulator::new();
+    accumulator.update(&results, Duration::ZERO);
+    let mut previous_time = Instant::now();
+    while running.load(Ordering::SeqCst) {
+        exporter.update(&results, accumulator.totals());
+        sleep(interval.saturating_sub(previous_time.elapsed()));
+
+        let elapsed = previous_time.elapsed();
+        previous_time = Instant::now();
+        collect(&mut sys, args.sampling.gpu, &filter, false, &mut results);
+        accumulator.update(&results, elapsed);
+    }
+}
+
+fn execute_command(
+    command: Vec<String>,
+    output: Option<String>,
+    scheduling: &ChildScheduling,
+    env: &[(&str, PathBuf)],
+    watcher: Option<&mut OutputWatcher>,
+) -> std::io::Result<Child> {
     if command.is_empty() {
         exit(1);
     }
@@ -188,56 +684,51 @@ fn execute_command(command: Vec<String>, output: Option<String>) -> std::io::Res
     for arg in command.iter().skip(1) {
         cmd.arg(arg);
     }
-    if output.is_some() {
-        cmd.stdout(Stdio::from(File::create(output.unwrap()).unwrap()));
-    }
-
-    return cmd.spawn();
-}
+    let stdout_file = output.map(|output| File::create(output).unwrap());
+    cmd.envs(env.iter().map(|(key, value)| (key, value)));
+    scheduling.apply(&mut cmd);
 
-fn collect(sys: &mut System, collect_gpu: bool, pid: u32, results: &mut HashMap<String, f64>) {
-    get_memory_usage(sys, results);
-    get_cpu_usage(sys, results);
-    get_cpu_counter(sys, results);
-    if collect_gpu {
-        get_gpu_counter(results);
+    match watcher {
+        Some(watcher) => {
+            watcher.capture(&mut cmd);
+            let mut child = cmd.spawn()?;
+            watcher.watch(&mut child, stdout_file);
+            Ok(child)
+        }
+        None => {
+            if let Some(file) = stdout_file {
+                cmd.stdout(Stdio::from(file));
+            }
+            cmd.spawn()
+        }
     }
-    // get_process_usage(sys, pid, results);
 }
 
-fn print_results(
-    time: SystemTime,
+fn collect(
+    sys: &mut System,
+    collect_gpu: bool,
+    filter: &MetricFilter,
+    overhead: bool,
     results: &mut HashMap<String, f64>,
-    sep: &str,
-    output: &mut dyn Write,
 ) {
-    output
-        .write_all(
-            format!(
-                "{}{}{}",
-                time.elapsed().unwrap().as_millis(),
-                sep,
-                time.duration_since(UNIX_EPOCH).unwrap().as_millis()
-            )
-            .as_bytes(),
-        )
-        .expect("Failed to write results");
-    for key in results.keys().sorted() {
-        output
-            .write_all(format!("{}{}", sep, results[key]).as_bytes())
-            .expect("Failed to write results");
-    }
-    output.write_all(b"\n").expect("Failed to write results");
-}
-
-fn print_header(results: &HashMap<String, f64>, sep: &str, output: &mut dyn Write) {
-    output
-        .write_all(format!("Delta{}Time", sep).as_bytes())
-        .expect("Failed to write header");
-    for key in results.keys().sorted() {
-        output
-            .write_all(format!("{}{}", sep, key).as_bytes())
-            .expect("Failed to write header");
+    // a metric that fails to be read is missing rather than repeating its last value
+    results.clear();
+    if simulate::is_active() {
+        // the scenario replaces the metrics of the system and the GPUs
+        simulate::collect(collect_gpu, filter, results);
+    } else {
+        if filter.allows_any(&memory::MEMORY_METRICS) {
+            get_memory_usage(sys, results);
+        }
+        // the overhead estimation relies on the global CPU usage
+        get_cpu_usage(sys, filter, overhead, results);
+        if collect_gpu {
+            get_gpu_counter(filter, results);
+        }
+    }
+    // the simulated MSRs are read through the CPU backends, the SMC cannot be simulated
+    if !cfg!(target_os = "macos") || !simulate::is_active() {
+        get_cpu_counter(sys, filter, results);
     }
-    output.write_all(b"\n").expect("Failed to write header");
+    filter.retain(results);
 }
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use sysinfo::{System, SystemExt};
 
+pub const MEMORY_METRICS: [&str; 4] = ["TOTAL_MEMORY", "USED_MEMORY", "TOTAL_SWAP", "USED_SWAP"];
+
 pub fn get_memory_usage(sys: &mut System, results: &mut HashMap<String, f64>) {
     sys.refresh_memory();
 
@@ -0,0 +1,55 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Information describing a run, saved next to the measurements so they can be
+/// interpreted and reproduced later.
+#[derive(Default, Serialize)]
+pub struct Metadata {
+    #[serde(skip)]
+    run_id: String,
+    #[serde(flatten)]
+    entries: BTreeMap<String, Value>,
+}
+
+impl Metadata {
+    pub fn new(command: &[String]) -> Self {
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let mut metadata = Metadata {
+            // unique enough to tell the runs of a machine apart
+            run_id: format!("{:x}-{:x}", start_time, std::process::id()),
+            ..Default::default()
+        };
+        metadata.insert("run_id", metadata.run_id.clone());
+        metadata.insert("version", env!("CARGO_PKG_VERSION"));
+        metadata.insert("os", std::env::consts::OS);
+        metadata.insert("command", command);
+        metadata.insert("start_time", start_time);
+        metadata
+    }
+
+    /// Identifier of the run, also attached to the exported metrics.
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    pub fn insert<T: Serialize>(&mut self, key: &str, value: T) {
+        let value = serde_json::to_value(value).expect("Failed to serialize metadata");
+        self.entries.insert(key.to_string(), value);
+    }
+
+    pub fn entries(&self) -> &BTreeMap<String, Value> {
+        &self.entries
+    }
+
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
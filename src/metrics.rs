@@ -0,0 +1,77 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How the CPU usage and frequency are reported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CpuDetail {
+    /// One column per logical CPU
+    #[default]
+    PerCore,
+    /// A single column averaged over all the CPUs
+    Aggregated,
+}
+
+/// Selects the metrics to collect using glob patterns on the column names.
+///
+/// The collectors use it to decide which sensors to poll, a sensor is only read if
+/// one of the metrics it provides is selected.
+#[derive(Clone, Debug, Default)]
+pub struct MetricFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    pub cpu_detail: CpuDetail,
+}
+
+impl MetricFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>, cpu_detail: CpuDetail) -> Self {
+        MetricFilter {
+            include,
+            exclude,
+            cpu_detail,
+        }
+    }
+
+    /// Returns true if the metric should be collected.
+    pub fn allows(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| glob_match(p, name)))
+            && !self.exclude.iter().any(|p| glob_match(p, name))
+    }
+
+    /// Returns true if at least one of the metrics should be collected.
+    pub fn allows_any<S: AsRef<str>>(&self, names: &[S]) -> bool {
+        names.iter().any(|name| self.allows(name.as_ref()))
+    }
+
+    /// Removes the metrics that are not selected.
+    pub fn retain(&self, results: &mut HashMap<String, f64>) {
+        results.retain(|name, _| self.allows(name));
+    }
+}
+
+/// Matches `name` against a glob pattern supporting `*` and `?`.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` in the pattern and of the name when it was reached
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // let the last `*` consume one more character
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use sysinfo::{CpuExt, System, SystemExt};
+
+use crate::metrics::MetricFilter;
+
+pub const CPU_TIME_KEY: &str = "ENERGIBRIDGE_CPU_TIME (s)";
+pub const ENERGY_KEY: &str = "ENERGIBRIDGE_ENERGY (J)";
+
+/// Estimates the resources consumed by EnergiBridge itself while it samples.
+///
+/// The energy of an interval is attributed to EnergiBridge proportionally to the share
+/// of the busy CPU time of the machine that was spent by EnergiBridge.
+pub struct Overhead {
+    start_cpu_time: Duration,
+    previous_cpu_time: Duration,
+    energy: f64,
+}
+
+impl Overhead {
+    pub fn new() -> Self {
+        let cpu_time = process_cpu_time();
+        Overhead {
+            start_cpu_time: cpu_time,
+            previous_cpu_time: cpu_time,
+            energy: 0.0,
+        }
+    }
+
+    /// Accounts for the interval that just ended, `cpu_energy` is the energy consumed by
+    /// the CPU during that interval if it is known. Only the metrics selected by the
+    /// filter are added to the results.
+    pub fn update(
+        &mut self,
+        sys: &System,
+        elapsed: Duration,
+        cpu_energy: Option<f64>,
+        filter: &MetricFilter,
+        results: &mut HashMap<String, f64>,
+    ) {
+        let cpu_time = process_cpu_time();
+        let own = cpu_time.saturating_sub(self.previous_cpu_time).as_secs_f64();
+        self.previous_cpu_time = cpu_time;
+
+        // cpu_usage is the average usage of all the cores in percent
+        let busy = sys.global_cpu_info().cpu_usage() as f64 / 100.0
+            * sys.cpus().len() as f64
+            * elapsed.as_secs_f64();
+        if let Some(energy) = cpu_energy {
+            if busy > 0.0 {
+                self.energy += energy * (own / busy).min(1.0);
+            }
+        }
+
+        if filter.allows(CPU_TIME_KEY) {
+            results.insert(CPU_TIME_KEY.to_string(), self.cpu_time().as_secs_f64());
+        }
+        if filter.allows(ENERGY_KEY) {
+            results.insert(ENERGY_KEY.to_string(), self.energy);
+        }
+    }
+
+    /// CPU time consumed by EnergiBridge since the overhead tracking started.
+    pub fn cpu_time(&self) -> Duration {
+        self.previous_cpu_time.saturating_sub(self.start_cpu_time)
+    }
+
+    /// Energy in joules attributed to EnergiBridge.
+    pub fn energy(&self) -> f64 {
+        self.energy
+    }
+}
+
+#[cfg(unix)]
+fn process_cpu_time() -> Duration {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return Duration::ZERO;
+    }
+    let to_duration = |t: libc::timeval| {
+        Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+    };
+    to_duration(usage.ru_utime) + to_duration(usage.ru_stime)
+}
+
+#[cfg(target_os = "windows")]
+fn process_cpu_time() -> Duration {
+    use windows::Win32::Foundation::FILETIME;
+    use windows::Win32::System::Threading::{GetCurrentProcess, GetProcessTimes};
+
+    let mut creation = FILETIME::default();
+    let mut exit = FILETIME::default();
+    let mut kernel = FILETIME::default();
+    let mut user = FILETIME::default();
+    let res = unsafe {
+        GetProcessTimes(
+            GetCurrentProcess(),
+            &mut creation,
+            &mut exit,
+            &mut kernel,
+            &mut user,
+        )
+    };
+    if res.is_err() {
+        return Duration::ZERO;
+    }
+    // FILETIME are expressed in 100ns ticks
+    let to_duration = |t: FILETIME| {
+        Duration::from_nanos((((t.dwHighDateTime as u64) << 32) | t.dwLowDateTime as u64) * 100)
+    };
+    to_duration(kernel) + to_duration(user)
+}
@@ -0,0 +1,58 @@
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use super::{PhaseEvent, PhaseSender};
+
+/// Environment variable giving the path of the marker channel to the command.
+pub const MARKER_ENV: &str = "ENERGIBRIDGE_MARKER";
+
+/// A named pipe where the measured command writes `begin <name>` and `end <name>` lines.
+pub struct MarkerChannel {
+    path: PathBuf,
+}
+
+impl MarkerChannel {
+    /// Creates the pipe and starts a thread forwarding the markers to `sender`.
+    pub fn open(sender: PhaseSender) -> io::Result<MarkerChannel> {
+        let path = std::env::temp_dir().join(format!("energibridge-{}.markers", std::process::id()));
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let reader_path = path.clone();
+        thread::spawn(move || {
+            // opening blocks until a writer opens the pipe, and reading stops when the
+            // last writer closes it, so reopen it to accept markers from other writers
+            while let Ok(file) = File::open(&reader_path) {
+                for line in BufReader::new(file).lines() {
+                    let Ok(line) = line else { break };
+                    match PhaseEvent::parse(&line) {
+                        Some(event) => {
+                            if sender.send(event).is_err() {
+                                return;
+                            }
+                        }
+                        None => eprintln!("[WARNING] Invalid phase marker: {}", line),
+                    }
+                }
+            }
+        });
+
+        Ok(MarkerChannel { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for MarkerChannel {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
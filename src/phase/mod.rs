@@ -0,0 +1,187 @@
+#[cfg(unix)]
+pub mod marker;
+pub mod output;
+
+use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+use std::time::{Duration, Instant};
+
+/// Marks the beginning or the end of a named phase of the measured command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PhaseEvent {
+    Begin(String),
+    End(String),
+}
+
+impl PhaseEvent {
+    /// Parses a marker line: `begin <name>` or `end <name>`.
+    pub fn parse(line: &str) -> Option<PhaseEvent> {
+        let (kind, name) = line.trim().split_once(char::is_whitespace)?;
+        let name = name.trim().to_string();
+        match kind {
+            "begin" => Some(PhaseEvent::Begin(name)),
+            "end" => Some(PhaseEvent::End(name)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct PhaseStats {
+    pub energy: f64,
+    pub duration: Duration,
+    pub count: u32,
+}
+
+/// Sends phase events to a [`PhaseTracker`], timestamped when they are sent.
+#[derive(Clone)]
+pub struct PhaseSender {
+    sender: Sender<(Instant, PhaseEvent)>,
+}
+
+impl PhaseSender {
+    pub fn send(&self, event: PhaseEvent) -> Result<(), SendError<(Instant, PhaseEvent)>> {
+        self.send_at(Instant::now(), event)
+    }
+
+    /// Sends an event that happened at `at`, e.g. when replaying a recording.
+    pub fn send_at(
+        &self,
+        at: Instant,
+        event: PhaseEvent,
+    ) -> Result<(), SendError<(Instant, PhaseEvent)>> {
+        self.sender.send((at, event))
+    }
+}
+
+/// Keeps track of the active phases and of the energy consumed during each of them.
+///
+/// The events are sent by the phase sources (marker channel, output of the command)
+/// and applied at each sample. The interval that just ended is split at the time of
+/// its events, and each part is attributed to the phases active during it with a
+/// share of the energy proportional to its duration.
+pub struct PhaseTracker {
+    sender: Sender<(Instant, PhaseEvent)>,
+    receiver: Receiver<(Instant, PhaseEvent)>,
+    active: Vec<String>,
+    // in the order in which the phases were first seen
+    stats: Vec<(String, PhaseStats)>,
+}
+
+impl PhaseTracker {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        PhaseTracker {
+            sender,
+            receiver,
+            active: Vec::new(),
+            stats: Vec::new(),
+        }
+    }
+
+    /// Returns a channel to send phase events from another thread.
+    pub fn sender(&self) -> PhaseSender {
+        PhaseSender {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Applies the pending events and attributes the interval of `elapsed` that ended at
+    /// `end`, during which `energy` was consumed.
+    pub fn update(&mut self, end: Instant, elapsed: Duration, energy: Option<f64>) {
+        let start = end.checked_sub(elapsed).unwrap_or(end);
+        let mut cursor = start;
+        while let Ok((at, event)) = self.receiver.try_recv() {
+            // the events sent while the sample was collected are applied at its end, the
+            // sources are polled concurrently so their events may be slightly out of order
+            let at = at.clamp(cursor, end);
+            self.charge(at - cursor, elapsed, energy);
+            cursor = at;
+            match event {
+                PhaseEvent::Begin(name) => {
+                    if !self.active.contains(&name) {
+                        self.active.push(name.clone());
+                        self.stats_mut(&name).count += 1;
+                    }
+                }
+                PhaseEvent::End(name) => self.active.retain(|n| *n != name),
+            }
+        }
+        self.charge(end - cursor, elapsed, energy);
+    }
+
+    // Attributes a part of `duration` of an interval of `elapsed` to the active phases.
+    fn charge(&mut self, duration: Duration, elapsed: Duration, energy: Option<f64>) {
+        if duration.is_zero() {
+            return;
+        }
+        let share = energy.unwrap_or(0.0) * duration.as_secs_f64() / elapsed.as_secs_f64();
+        for name in self.active.clone() {
+            let stats = self.stats_mut(&name);
+            stats.duration += duration;
+            stats.energy += share;
+        }
+    }
+
+    /// The phases active after the last update, separated by `+`.
+    pub fn label(&self) -> String {
+        self.active.join("+")
+    }
+
+    pub fn stats(&self) -> &[(String, PhaseStats)] {
+        &self.stats
+    }
+
+    fn stats_mut(&mut self, name: &str) -> &mut PhaseStats {
+        let index = match self.stats.iter().position(|(n, _)| n == name) {
+            Some(index) => index,
+            None => {
+                self.stats.push((name.to_string(), PhaseStats::default()));
+                self.stats.len() - 1
+            }
+        };
+        &mut self.stats[index].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats<'a>(tracker: &'a PhaseTracker, name: &str) -> &'a PhaseStats {
+        &tracker.stats().iter().find(|(n, _)| n == name).unwrap().1
+    }
+
+    #[test]
+    fn back_to_back_phases_split_the_interval() {
+        let mut tracker = PhaseTracker::new();
+        let start = Instant::now();
+        let sender = tracker.sender();
+        sender.send_at(start, PhaseEvent::Begin("a".into())).unwrap();
+        tracker.update(start, Duration::ZERO, None);
+
+        let at = start + Duration::from_millis(250);
+        sender.send_at(at, PhaseEvent::End("a".into())).unwrap();
+        sender.send_at(at, PhaseEvent::Begin("b".into())).unwrap();
+        tracker.update(start + Duration::from_secs(1), Duration::from_secs(1), Some(8.0));
+
+        assert_eq!(stats(&tracker, "a").duration, Duration::from_millis(250));
+        assert_eq!(stats(&tracker, "a").energy, 2.0);
+        assert_eq!(stats(&tracker, "b").duration, Duration::from_millis(750));
+        assert_eq!(stats(&tracker, "b").energy, 6.0);
+        assert_eq!(tracker.label(), "b");
+    }
+
+    #[test]
+    fn late_events_apply_at_the_end_of_the_interval() {
+        let mut tracker = PhaseTracker::new();
+        let end = Instant::now();
+        tracker
+            .sender()
+            .send_at(end + Duration::from_millis(5), PhaseEvent::Begin("a".into()))
+            .unwrap();
+        tracker.update(end, Duration::from_secs(1), Some(1.0));
+        assert_eq!(stats(&tracker, "a").duration, Duration::ZERO);
+        assert_eq!(stats(&tracker, "a").count, 1);
+        assert_eq!(tracker.label(), "a");
+    }
+}
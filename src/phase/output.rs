@@ -0,0 +1,150 @@
+use regex::Regex;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{PhaseEvent, PhaseSender};
+
+// Prefix of the phases detected in the output, so that closing one of them never closes
+// a phase with the same name opened by a marker.
+const OUTPUT_NAMESPACE: &str = "output:";
+
+// Time left to the readers to copy the rest of the output once the command has exited,
+// a process started by the command may keep the pipes open long after it.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Regular expressions detecting the phases in the output of the command.
+///
+/// A line matching a begin pattern opens the phase named after the first capture group
+/// of the pattern, or after the pattern itself if it has none, prefixed with `output:`.
+/// Since the tools usually print the name of each step when it starts, opening a phase
+/// closes the previous one. A line matching an end pattern closes the current phase.
+pub struct PhasePatterns {
+    begin: Vec<Regex>,
+    end: Vec<Regex>,
+}
+
+impl PhasePatterns {
+    pub fn new(begin: &[String], end: &[String]) -> Result<Self, regex::Error> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| Regex::new(p))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(PhasePatterns {
+            begin: compile(begin)?,
+            end: compile(end)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.begin.is_empty() && self.end.is_empty()
+    }
+
+    fn find(patterns: &[Regex], line: &str) -> Option<String> {
+        patterns.iter().find_map(|pattern| {
+            let captures = pattern.captures(line)?;
+            let name = match captures.get(1) {
+                Some(group) => group.as_str(),
+                None => pattern.as_str(),
+            };
+            Some(format!("{}{}", OUTPUT_NAMESPACE, name))
+        })
+    }
+}
+
+/// Copies the output of the command to its destination while looking for phases.
+pub struct OutputWatcher {
+    patterns: Arc<PhasePatterns>,
+    sender: PhaseSender,
+    // the phase opened by the patterns, shared by stdout and stderr
+    current: Arc<Mutex<Option<String>>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl OutputWatcher {
+    pub fn new(patterns: PhasePatterns, sender: PhaseSender) -> Self {
+        OutputWatcher {
+            patterns: Arc::new(patterns),
+            sender,
+            current: Arc::new(Mutex::new(None)),
+            threads: Vec::new(),
+        }
+    }
+
+    /// Captures the output of the command, must be called before it is spawned.
+    pub fn capture(&self, cmd: &mut Command) {
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+    }
+
+    /// Starts copying the output of the spawned command, stdout goes to `stdout_file`
+    /// if it is set and to the console otherwise.
+    pub fn watch(&mut self, child: &mut Child, stdout_file: Option<File>) {
+        if let Some(stdout) = child.stdout.take() {
+            let writer: Box<dyn Write + Send> = match stdout_file {
+                Some(file) => Box::new(file),
+                None => Box::new(io::stdout()),
+            };
+            self.spawn(stdout, writer);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            self.spawn(stderr, Box::new(io::stderr()));
+        }
+    }
+
+    /// Waits until the whole output of the command has been copied, or gives up after
+    /// a while if the pipes are still open once the command has exited.
+    pub fn finish(&mut self) {
+        let deadline = Instant::now() + FINISH_TIMEOUT;
+        while self.threads.iter().any(|thread| !thread.is_finished()) {
+            if Instant::now() >= deadline {
+                eprintln!(
+                    "[WARNING] The output of the command is still open, it is no longer watched."
+                );
+                // the readers are detached, they stop with the process
+                self.threads.clear();
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+
+    fn spawn(&mut self, reader: impl Read + Send + 'static, mut writer: Box<dyn Write + Send>) {
+        let patterns = self.patterns.clone();
+        let sender = self.sender.clone();
+        let current = self.current.clone();
+        self.threads.push(thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                }
+                let _ = writer.write_all(&line).and_then(|_| writer.flush());
+
+                let text = String::from_utf8_lossy(&line);
+                let mut current = current.lock().unwrap();
+                if let Some(name) = PhasePatterns::find(&patterns.begin, &text) {
+                    if let Some(previous) = current.replace(name.clone()) {
+                        let _ = sender.send(PhaseEvent::End(previous));
+                    }
+                    let _ = sender.send(PhaseEvent::Begin(name));
+                } else if PhasePatterns::find(&patterns.end, &text).is_some() {
+                    if let Some(previous) = current.take() {
+                        let _ = sender.send(PhaseEvent::End(previous));
+                    }
+                }
+            }
+        }));
+    }
+}
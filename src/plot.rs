@@ -0,0 +1,236 @@
+use once_cell::sync::OnceCell;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::register_font;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::path::Path;
+use thiserror::Error;
+
+use crate::sensor::{self, Sensor};
+
+// Font of the charts, embedded so that they can be drawn on any machine (see
+// assets/fonts/LICENSE).
+const FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+
+// Height of each of the stacked charts, in pixels.
+const PANEL_HEIGHT: u32 = 300;
+const WIDTH: u32 = 1200;
+
+#[derive(Error, Debug)]
+pub enum PlotError {
+    #[error("failed to load the font of the chart")]
+    Font,
+    #[error("no power, usage or temperature metric to plot")]
+    Empty,
+    #[error("failed to draw the chart: {0}")]
+    Draw(String),
+}
+
+fn draw_error<E: Display>(e: E) -> PlotError {
+    PlotError::Draw(e.to_string())
+}
+
+type Series = BTreeMap<String, Vec<(f64, f64)>>;
+
+/// Time series of a run drawn as stacked charts: the power of each energy domain, the
+/// usage and the temperature of the devices, with the phases shaded.
+#[derive(Default)]
+pub struct Chart {
+    start: Option<u64>,
+    previous: Option<(f64, HashMap<String, f64>)>,
+    end: f64,
+    power: Series,
+    usage: Series,
+    temperature: Series,
+    phase: String,
+    phases: Vec<(f64, f64, String)>,
+}
+
+impl Chart {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a sample taken at `time` (Unix time in milliseconds), `totals` is the
+    /// energy consumed by each domain so far.
+    pub fn add(
+        &mut self,
+        time: u64,
+        results: &HashMap<String, f64>,
+        totals: &HashMap<String, f64>,
+        phase: Option<&str>,
+    ) {
+        let start = *self.start.get_or_insert(time);
+        let t = time.saturating_sub(start) as f64 / 1000.0;
+        self.end = self.end.max(t);
+
+        // the power of a domain is its energy over the interval since the previous sample
+        if let Some((previous, previous_totals)) = &self.previous {
+            let elapsed = t - previous;
+            if elapsed > 0.0 {
+                for (key, total) in totals {
+                    if let Some(previous_total) = previous_totals.get(key) {
+                        self.power
+                            .entry(sensor::base_name(key).to_string())
+                            .or_default()
+                            .push((t, (total - previous_total) / elapsed));
+                    }
+                }
+                self.previous = Some((t, totals.clone()));
+            }
+        } else {
+            self.previous = Some((t, totals.clone()));
+        }
+
+        // the usage and temperature are averaged over the cores and devices of a kind
+        let mut means: BTreeMap<(bool, String), (f64, u32)> = BTreeMap::new();
+        for (key, value) in results {
+            let temperature = match sensor::unit(key).as_deref() {
+                Some("%") => false,
+                Some("°C") => true,
+                _ => continue,
+            };
+            let mean = means
+                .entry((temperature, Sensor::parse(key).name))
+                .or_default();
+            mean.0 += value;
+            mean.1 += 1;
+        }
+        for ((temperature, name), (sum, count)) in means {
+            let series = if temperature { &mut self.temperature } else { &mut self.usage };
+            series.entry(name).or_default().push((t, sum / count as f64));
+        }
+
+        let label = phase.unwrap_or_default();
+        if !self.phase.is_empty() {
+            if let Some(span) = self.phases.last_mut() {
+                span.1 = t;
+            }
+        }
+        if label != self.phase {
+            if !label.is_empty() {
+                self.phases.push((t, t, label.to_string()));
+            }
+            self.phase = label.to_string();
+        }
+    }
+
+    /// Draws the chart to a PNG or SVG file, according to its extension.
+    pub fn save(&self, path: &Path) -> Result<(), PlotError> {
+        load_font()?;
+        let size = self.size()?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("png") => self.render(BitMapBackend::new(path, size).into_drawing_area()),
+            _ => self.render(SVGBackend::new(path, size).into_drawing_area()),
+        }
+    }
+
+    /// Draws the chart as an SVG document.
+    pub fn to_svg(&self) -> Result<String, PlotError> {
+        load_font()?;
+        let size = self.size()?;
+        let mut svg = String::new();
+        self.render(SVGBackend::with_string(&mut svg, size).into_drawing_area())?;
+        Ok(svg)
+    }
+
+    fn panels(&self) -> Vec<(&'static str, &Series)> {
+        [
+            ("Power (W)", &self.power),
+            ("Usage (%)", &self.usage),
+            ("Temperature (°C)", &self.temperature),
+        ]
+        .into_iter()
+        .filter(|(_, series)| !series.is_empty())
+        .collect()
+    }
+
+    fn size(&self) -> Result<(u32, u32), PlotError> {
+        match self.panels().len() {
+            0 => Err(PlotError::Empty),
+            panels => Ok((WIDTH, PANEL_HEIGHT * panels as u32)),
+        }
+    }
+
+    fn render<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<(), PlotError> {
+        root.fill(&WHITE).map_err(draw_error)?;
+        let panels = self.panels();
+        let areas = root.split_evenly((panels.len(), 1));
+        // a phase keeps the same color in all the charts
+        let mut labels: Vec<&str> = Vec::new();
+        for (_, _, label) in &self.phases {
+            if !labels.contains(&label.as_str()) {
+                labels.push(label);
+            }
+        }
+
+        for (index, ((description, series), area)) in panels.iter().zip(areas).enumerate() {
+            let max = series
+                .values()
+                .flatten()
+                .fold(0.0f64, |max, (_, value)| max.max(*value));
+            let max = if max > 0.0 { max * 1.1 } else { 1.0 };
+            let mut chart = ChartBuilder::on(&area)
+                .margin(10)
+                .x_label_area_size(30)
+                .y_label_area_size(60)
+                .build_cartesian_2d(0.0..self.end.max(1.0), 0.0..max)
+                .map_err(draw_error)?;
+            chart
+                .configure_mesh()
+                .light_line_style(TRANSPARENT)
+                .x_desc("Time (s)")
+                .y_desc(*description)
+                .draw()
+                .map_err(draw_error)?;
+
+            for (start, end, label) in &self.phases {
+                let color = Palette99::pick(labels.iter().position(|l| l == label).unwrap_or(0));
+                chart
+                    .draw_series(std::iter::once(Rectangle::new(
+                        [(*start, 0.0), (*end, max)],
+                        color.mix(0.15).filled(),
+                    )))
+                    .map_err(draw_error)?;
+                if index == 0 {
+                    chart
+                        .draw_series(std::iter::once(Text::new(
+                            label.clone(),
+                            (*start, max),
+                            ("sans-serif", 14),
+                        )))
+                        .map_err(draw_error)?;
+                }
+            }
+
+            for (i, (name, points)) in series.iter().enumerate() {
+                let color = Palette99::pick(i).to_rgba();
+                chart
+                    .draw_series(LineSeries::new(points.iter().copied(), color.stroke_width(2)))
+                    .map_err(draw_error)?
+                    .label(name.as_str())
+                    .legend(move |(x, y)| {
+                        PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+                    });
+            }
+            chart
+                .configure_series_labels()
+                .background_style(WHITE.mix(0.8))
+                .border_style(BLACK)
+                .draw()
+                .map_err(draw_error)?;
+        }
+        root.present().map_err(draw_error)
+    }
+}
+
+// Registers the font used by the charts, once.
+fn load_font() -> Result<(), PlotError> {
+    static LOADED: OnceCell<()> = OnceCell::new();
+    LOADED
+        .get_or_try_init(|| {
+            register_font("sans-serif", FontStyle::Normal, FONT).map_err(|_| PlotError::Font)
+        })
+        .copied()
+}
@@ -0,0 +1,140 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::sync::Mutex;
+
+/// A column name split into the name of the metric, its unit and the index of the
+/// device it was measured on, e.g. `GPU1_POWER (mWatts)` is the `GPU_POWER` metric
+/// in `mWatts` of the GPU 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sensor {
+    pub name: String,
+    pub unit: Option<String>,
+    pub labels: Vec<(&'static str, String)>,
+}
+
+// RAPL domains measured per package
+const PACKAGE_DOMAINS: [&str; 5] = ["PACKAGE_ENERGY", "DRAM_ENERGY", "PP0_ENERGY", "PP1_ENERGY", "CPU_ENERGY"];
+
+impl Sensor {
+    pub fn parse(key: &str) -> Sensor {
+        let (name, unit) = match key.rsplit_once(" (") {
+            Some((name, unit)) if unit.ends_with(')') => {
+                (name, Some(unit.trim_end_matches(')').to_string()))
+            }
+            _ => (key, None),
+        };
+
+        let mut labels = Vec::new();
+        let mut name = name.to_string();
+        for (prefix, label) in [("CORE", "core"), ("GPU", "gpu")] {
+            if let Some(index) = device_index(&name, prefix) {
+                name = format!("{}{}", prefix, &name[prefix.len() + index.len()..]);
+                labels.push((label, index));
+            }
+        }
+        if let Some((base, index)) = name.rsplit_once('_') {
+            if !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()) {
+                let label = match base {
+                    "CPU_USAGE" | "CPU_FREQUENCY" => "core",
+                    _ => "index",
+                };
+                labels.push((label, index.to_string()));
+                name = base.to_string();
+            }
+        }
+        if PACKAGE_DOMAINS.contains(&name.as_str()) {
+            // only the first package is measured
+            labels.push(("socket", "0".to_string()));
+        }
+
+        Sensor { name, unit, labels }
+    }
+}
+
+// Returns the index following `prefix` in names like CORE12_ENERGY.
+fn device_index(name: &str, prefix: &str) -> Option<String> {
+    let rest = name.strip_prefix(prefix)?;
+    let index: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if index.is_empty() || !rest[index.len()..].starts_with('_') {
+        return None;
+    }
+    Some(index)
+}
+
+/// The name of a metric without its unit suffix, e.g. `CORE0_ENERGY` for `CORE0_ENERGY (J)`.
+pub fn base_name(key: &str) -> &str {
+    match key.rsplit_once(" (") {
+        Some((name, unit)) if unit.ends_with(')') => name,
+        _ => key,
+    }
+}
+
+/// The unit of a metric, from the suffix of its name or inferred for the metrics
+/// that do not have one.
+pub fn unit(key: &str) -> Option<String> {
+    let sensor = Sensor::parse(key);
+    if sensor.unit.is_some() {
+        return sensor.unit;
+    }
+    let unit = match sensor.name.as_str() {
+        "CPU_USAGE" | "GPU_USAGE" => "%",
+        "CPU_FREQUENCY" => "MHz",
+        "CPU_TEMP" | "GPU_TEMPERATURE" => "°C",
+        "TOTAL_MEMORY" | "USED_MEMORY" | "TOTAL_SWAP" | "USED_SWAP" => "bytes",
+        "GPU_MEMORY_USED" | "GPU_MEMORY_TOTAL" => "MiB",
+        _ => return None,
+    };
+    Some(unit.to_string())
+}
+
+/// Warns that a metric could not be read, once per metric. The metric is left out of the
+/// sample, which the outputs report as a missing value.
+pub fn warn_read_failure(key: &str, error: impl Display) {
+    static FAILED: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+    if FAILED.lock().unwrap().insert(key.to_string()) {
+        eprintln!("[WARNING] Failed to read {}: {}, it is reported as missing.", key, error);
+    }
+}
+
+/// A metric that can be measured on this machine, as listed by `list-sensors`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SensorInfo {
+    /// Column name of the metric, or the name of the device for the metrics that are
+    /// not recorded.
+    pub metric: String,
+    pub unit: Option<String>,
+    /// Backend and register or file the value is read from.
+    pub source: String,
+    /// How often the value is refreshed by the hardware or the driver.
+    pub update_rate: &'static str,
+    /// Privileges needed to read the value, if any.
+    pub privileges: Option<&'static str>,
+    /// Whether the value can be read with the current privileges.
+    pub readable: bool,
+    /// Whether EnergiBridge records the metric.
+    pub recorded: bool,
+}
+
+impl SensorInfo {
+    /// Describes a metric recorded by EnergiBridge, readable without extra privileges.
+    pub fn new(metric: &str, source: impl Into<String>, update_rate: &'static str) -> Self {
+        SensorInfo {
+            metric: metric.to_string(),
+            unit: unit(metric),
+            source: source.into(),
+            update_rate,
+            privileges: None,
+            readable: true,
+            recorded: true,
+        }
+    }
+
+    /// Marks the metric as requiring `privileges`.
+    pub fn requires(mut self, privileges: &'static str, readable: bool) -> Self {
+        self.privileges = Some(privileges);
+        self.readable = readable;
+        self
+    }
+}
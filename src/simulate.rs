@@ -0,0 +1,419 @@
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use thiserror::Error;
+
+use crate::metrics::MetricFilter;
+use crate::sensor::warn_read_failure;
+
+#[derive(Error, Debug)]
+pub enum SimulateError {
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("failed to parse {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+    #[error("unknown vendor `{0}`, expected GenuineIntel, AuthenticAMD or HygonGenuine")]
+    UnknownVendor(String),
+    #[error("unknown domain `{0}` for a {1} CPU, expected one of {2}")]
+    UnknownDomain(String, String, String),
+    #[error("the points of a curve must be sorted by time")]
+    Unsorted,
+}
+
+/// A value over time: a constant or the points `[seconds, value]` of a piecewise linear
+/// curve, constant before its first point and after its last one.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Curve {
+    Constant(f64),
+    Points(Vec<(f64, f64)>),
+}
+
+impl Curve {
+    /// The value at `t` seconds.
+    pub fn value(&self, t: f64) -> f64 {
+        match self {
+            Curve::Constant(value) => *value,
+            Curve::Points(points) => {
+                let Some(first) = points.first() else {
+                    return 0.0;
+                };
+                if t <= first.0 {
+                    return first.1;
+                }
+                for pair in points.windows(2) {
+                    let ((t0, v0), (t1, v1)) = (pair[0], pair[1]);
+                    if t <= t1 {
+                        return v0 + (v1 - v0) * (t - t0) / (t1 - t0);
+                    }
+                }
+                points.last().unwrap().1
+            }
+        }
+    }
+
+    /// The integral of the curve from 0 to `t` seconds.
+    pub fn integral(&self, t: f64) -> f64 {
+        match self {
+            Curve::Constant(value) => value * t,
+            Curve::Points(points) => {
+                // the curve is linear between the points, and constant before and after them
+                let mut bounds = vec![0.0];
+                bounds.extend(points.iter().map(|(time, _)| *time).filter(|time| *time > 0.0 && *time < t));
+                bounds.push(t);
+                bounds
+                    .windows(2)
+                    .map(|pair| (pair[1] - pair[0]) * (self.value(pair[0]) + self.value(pair[1])) / 2.0)
+                    .sum()
+            }
+        }
+    }
+
+    fn is_sorted(&self) -> bool {
+        match self {
+            Curve::Constant(_) => true,
+            Curve::Points(points) => points.windows(2).all(|pair| pair[0].0 < pair[1].0),
+        }
+    }
+}
+
+/// An energy domain of the CPU.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Domain {
+    /// Power in Watts.
+    pub power: Curve,
+    /// Seconds after which the energy counter wraps around.
+    pub wrap_after: Option<f64>,
+    /// Seconds after which reading the counter fails.
+    pub fail_after: Option<f64>,
+}
+
+/// A GPU answering in place of NVML, only the given metrics are reported.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Gpu {
+    /// Power in Watts.
+    pub power: Option<Curve>,
+    /// Utilization in %.
+    pub usage: Option<Curve>,
+    /// Temperature in °C.
+    pub temperature: Option<Curve>,
+    /// Memory in MiB.
+    pub memory_used: Option<Curve>,
+    pub memory_total: Option<f64>,
+    /// Seconds after which the GPU stops answering.
+    pub fail_after: Option<f64>,
+}
+
+/// Synthetic hardware replacing the sensors of the machine, to test EnergiBridge where
+/// there is no RAPL or GPU.
+///
+/// ```toml
+/// vendor = "GenuineIntel"
+/// family = 6
+/// model = 0x9E
+///
+/// [domains.PACKAGE]
+/// power = [[0, 10], [5, 40], [10, 10]]
+/// wrap_after = 3
+///
+/// [[gpus]]
+/// power = 120
+///
+/// [metrics]
+/// USED_MEMORY = 4e9
+/// ```
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// CPU vendor whose MSRs are simulated, GenuineIntel, AuthenticAMD or HygonGenuine.
+    #[serde(default = "default_vendor")]
+    pub vendor: String,
+    /// CPUID family and model of the CPU, which select the domains that are read. The
+    /// unknown models read PACKAGE, DRAM, PP0 and PP1 on Intel.
+    #[serde(default)]
+    pub family: u32,
+    #[serde(default)]
+    pub model: u32,
+    /// Energy domains by name: PACKAGE, DRAM, PP0, PP1 and PSYS for Intel, PACKAGE and
+    /// CORE (every core) for AMD. The domains that are not given consume no energy.
+    #[serde(default)]
+    pub domains: BTreeMap<String, Domain>,
+    #[serde(default)]
+    pub gpus: Vec<Gpu>,
+    /// Values of the other metrics by column name, they replace the metrics of the system.
+    #[serde(default)]
+    pub metrics: BTreeMap<String, Curve>,
+}
+
+fn default_vendor() -> String {
+    "GenuineIntel".to_string()
+}
+
+// The simulated scenario and the time it started.
+static SIMULATION: OnceCell<(Scenario, Instant)> = OnceCell::new();
+
+/// Loads a scenario and replaces the sensors of the machine with it.
+pub fn start(path: &Path) -> Result<(), SimulateError> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| SimulateError::Io(path.to_path_buf(), e))?;
+    let scenario: Scenario =
+        toml::from_str(&content).map_err(|e| SimulateError::Parse(path.to_path_buf(), e))?;
+    let curves = scenario
+        .domains
+        .values()
+        .map(|domain| &domain.power)
+        .chain(scenario.metrics.values())
+        .chain(scenario.gpus.iter().flat_map(|gpu| {
+            [&gpu.power, &gpu.usage, &gpu.temperature, &gpu.memory_used]
+                .into_iter()
+                .flatten()
+        }));
+    if !curves.into_iter().all(Curve::is_sorted) {
+        return Err(SimulateError::Unsorted);
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    crate::cpu::msr::set_msr_reader(Box::new(msr::SimulatedMsr::new(&scenario)?));
+    #[cfg(target_os = "macos")]
+    if !scenario.domains.is_empty() {
+        eprintln!("[WARNING] The CPU energy domains can only be simulated on Linux and Windows.");
+    }
+    let _ = SIMULATION.set((scenario, Instant::now()));
+    Ok(())
+}
+
+/// Whether the sensors are simulated.
+pub fn is_active() -> bool {
+    SIMULATION.get().is_some()
+}
+
+// Seconds since the start of the simulation.
+fn elapsed() -> f64 {
+    SIMULATION
+        .get()
+        .map_or(0.0, |(_, start)| start.elapsed().as_secs_f64())
+}
+
+/// Collects the metrics of the scenario, in place of the memory and CPU usage of the
+/// system, and of its GPUs if `collect_gpu` is set.
+pub fn collect(collect_gpu: bool, filter: &MetricFilter, results: &mut HashMap<String, f64>) {
+    let Some((scenario, _)) = SIMULATION.get() else {
+        return;
+    };
+    let t = elapsed();
+    for (key, curve) in &scenario.metrics {
+        if filter.allows(key) {
+            results.insert(key.clone(), curve.value(t));
+        }
+    }
+    if !collect_gpu {
+        return;
+    }
+    for (index, gpu) in scenario.gpus.iter().enumerate() {
+        let metrics = [
+            (format!("GPU{}_USAGE", index), gpu.usage.as_ref().map(|c| c.value(t))),
+            (format!("GPU{}_MEMORY_USED", index), gpu.memory_used.as_ref().map(|c| c.value(t))),
+            (format!("GPU{}_MEMORY_TOTAL", index), gpu.memory_total),
+            (format!("GPU{}_TEMPERATURE", index), gpu.temperature.as_ref().map(|c| c.value(t))),
+            // NVML reports the power in milliwatts
+            (format!("GPU{}_POWER (mWatts)", index), gpu.power.as_ref().map(|c| c.value(t) * 1000.0)),
+        ];
+        let failed = gpu.fail_after.is_some_and(|after| t >= after);
+        for (key, value) in metrics {
+            let Some(value) = value.filter(|_| filter.allows(&key)) else {
+                continue;
+            };
+            if failed {
+                warn_read_failure(&key, format_args!("simulated failure of GPU{}", index));
+            } else {
+                results.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+mod msr {
+    use std::collections::HashMap;
+    use std::io::{self, Error, ErrorKind};
+
+    use super::{elapsed, Curve, Scenario, SimulateError};
+    use crate::cpu::model::{microarchitecture, CpuId};
+    use crate::cpu::msr::MsrReader;
+
+    // The energy status MSRs are 32 bits counters
+    const COUNTER_RANGE: f64 = 4294967296.0;
+
+    // The registers of the CPUs of a vendor.
+    struct Layout {
+        // energy status MSR of each domain
+        domains: &'static [(&'static str, u32)],
+        // registers holding a constant value
+        registers: &'static [(u32, u64)],
+        // the energy unit is 2^-energy_unit_bits J
+        energy_unit_bits: i32,
+    }
+
+    // The unit registers hold an energy unit of 2^-14 J for Intel and 2^-16 J for AMD,
+    // the AMD P-state registers a frequency of 2400 MHz at 1.15 V.
+    const INTEL: Layout = Layout {
+        domains: &[
+            ("PACKAGE", 0x611),
+            ("DRAM", 0x619),
+            ("PP0", 0x639),
+            ("PP1", 0x641),
+            ("PSYS", 0x64D),
+        ],
+        registers: &[(0x606, 0xA0E03)],
+        energy_unit_bits: 14,
+    };
+    const AMD: Layout = Layout {
+        domains: &[("PACKAGE", 0xC001029B), ("CORE", 0xC001029A)],
+        registers: &[
+            (0xC0010299, 0xA1003),
+            (0xC0010293, 0x60 | (8 << 8) | (64 << 14)),
+            (0xC0010063, 0),
+        ],
+        energy_unit_bits: 16,
+    };
+
+    struct Counter {
+        name: String,
+        power: Curve,
+        // energy unit of the counter in joules
+        unit: f64,
+        // counter value in energy units at the start of the simulation
+        start: f64,
+        fail_after: Option<f64>,
+    }
+
+    /// Energy status MSRs following the power curves of a scenario.
+    pub struct SimulatedMsr {
+        cpu: CpuId,
+        registers: HashMap<u32, u64>,
+        counters: HashMap<u32, Counter>,
+    }
+
+    impl SimulatedMsr {
+        pub fn new(scenario: &Scenario) -> Result<Self, SimulateError> {
+            let layout = match scenario.vendor.as_str() {
+                "GenuineIntel" => INTEL,
+                "AuthenticAMD" | "HygonGenuine" => AMD,
+                vendor => return Err(SimulateError::UnknownVendor(vendor.to_string())),
+            };
+            let cpu = CpuId {
+                vendor: scenario.vendor.clone(),
+                family: scenario.family,
+                model: scenario.model,
+            };
+            let (domains, energy_unit) = (layout.domains, 0.5f64.powi(layout.energy_unit_bits));
+            // some server parts count the DRAM energy in a fixed unit
+            let dram_unit = microarchitecture(&cpu)
+                .and_then(|arch| arch.dram_energy_unit)
+                .unwrap_or(energy_unit);
+            let unit = |name: &str| if name == "DRAM" { dram_unit } else { energy_unit };
+            let mut counters = HashMap::new();
+            for (name, domain) in &scenario.domains {
+                let Some((_, msr)) = domains.iter().find(|(n, _)| n == name) else {
+                    let names: Vec<&str> = domains.iter().map(|(n, _)| *n).collect();
+                    return Err(SimulateError::UnknownDomain(
+                        name.clone(),
+                        scenario.vendor.clone(),
+                        names.join(", "),
+                    ));
+                };
+                // the counter starts close enough to its limit to wrap around at wrap_after
+                let start = domain.wrap_after.map_or(0.0, |after| {
+                    COUNTER_RANGE - (domain.power.integral(after) / unit(name)) % COUNTER_RANGE
+                });
+                counters.insert(
+                    *msr,
+                    Counter {
+                        name: name.clone(),
+                        power: domain.power.clone(),
+                        unit: unit(name),
+                        start,
+                        fail_after: domain.fail_after,
+                    },
+                );
+            }
+            // the other domains of the vendor are idle
+            for (name, msr) in domains {
+                counters.entry(*msr).or_insert_with(|| Counter {
+                    name: name.to_string(),
+                    power: Curve::Constant(0.0),
+                    unit: energy_unit,
+                    start: 0.0,
+                    fail_after: None,
+                });
+            }
+            Ok(SimulatedMsr {
+                cpu,
+                registers: layout.registers.iter().copied().collect(),
+                counters,
+            })
+        }
+    }
+
+    impl MsrReader for SimulatedMsr {
+        fn read(&mut self, msr: u32, _core: u32) -> io::Result<u64> {
+            if let Some(value) = self.registers.get(&msr) {
+                return Ok(*value);
+            }
+            let counter = self.counters.get(&msr).ok_or_else(|| {
+                Error::new(ErrorKind::NotFound, format!("the MSR {:#x} is not simulated", msr))
+            })?;
+            let t = elapsed();
+            if counter.fail_after.is_some_and(|after| t >= after) {
+                return Err(Error::other(format!("simulated failure of {}", counter.name)));
+            }
+            let units = counter.start + counter.power.integral(t) / counter.unit;
+            Ok((units % COUNTER_RANGE) as u64)
+        }
+
+        fn cpu(&self) -> Option<CpuId> {
+            Some(self.cpu.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Curve;
+
+    #[test]
+    fn constant_curve() {
+        let curve = Curve::Constant(2.5);
+        assert_eq!(curve.value(0.0), 2.5);
+        assert_eq!(curve.value(100.0), 2.5);
+        assert_eq!(curve.integral(4.0), 10.0);
+    }
+
+    #[test]
+    fn curve_values() {
+        let curve = Curve::Points(vec![(2.0, 10.0), (4.0, 20.0), (8.0, 0.0)]);
+        // constant before the first point and after the last one
+        assert_eq!(curve.value(0.0), 10.0);
+        assert_eq!(curve.value(2.0), 10.0);
+        assert_eq!(curve.value(3.0), 15.0);
+        assert_eq!(curve.value(4.0), 20.0);
+        assert_eq!(curve.value(7.0), 5.0);
+        assert_eq!(curve.value(10.0), 0.0);
+        assert_eq!(Curve::Points(Vec::new()).value(1.0), 0.0);
+    }
+
+    #[test]
+    fn curve_integrals() {
+        let curve = Curve::Points(vec![(2.0, 10.0), (4.0, 20.0), (8.0, 0.0)]);
+        assert_eq!(curve.integral(0.0), 0.0);
+        assert_eq!(curve.integral(1.0), 10.0);
+        assert_eq!(curve.integral(3.0), 20.0 + 12.5);
+        assert_eq!(curve.integral(4.0), 20.0 + 30.0);
+        assert_eq!(curve.integral(6.0), 50.0 + 30.0);
+        assert_eq!(curve.integral(10.0), 50.0 + 40.0);
+    }
+}
@@ -0,0 +1,96 @@
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::UNIX_EPOCH;
+
+use super::{Sample, Sink};
+
+/// Writes the samples as CSV, one column per metric.
+pub struct CsvSink {
+    output: Box<dyn Write>,
+    sep: String,
+    phase: bool,
+    // metrics of the header, in the order of the columns
+    keys: Vec<String>,
+}
+
+impl CsvSink {
+    /// `phase` adds a column with the active phases.
+    pub fn new(output: Box<dyn Write>, sep: &str, phase: bool) -> Self {
+        CsvSink {
+            output,
+            sep: sep.to_string(),
+            phase,
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl Sink for CsvSink {
+    fn header(&mut self, results: &HashMap<String, f64>) {
+        self.keys = print_header(results, &self.sep, self.phase, &mut self.output);
+    }
+
+    fn write(&mut self, sample: &Sample) {
+        print_results(sample, &self.keys, &self.sep, &mut self.output);
+    }
+
+    fn finish(&mut self) {
+        self.output.flush().expect("Failed to write results");
+    }
+}
+
+/// Writes a sample as a CSV row with the metrics of the header `keys`, the metrics
+/// missing from the sample are left empty.
+pub fn print_results(sample: &Sample, keys: &[String], sep: &str, output: &mut dyn Write) {
+    output
+        .write_all(
+            format!(
+                "{}{}{}",
+                sample.delta.as_millis(),
+                sep,
+                sample.time.duration_since(UNIX_EPOCH).unwrap().as_millis()
+            )
+            .as_bytes(),
+        )
+        .expect("Failed to write results");
+    for key in keys {
+        let value = sample.results.get(key).map(f64::to_string).unwrap_or_default();
+        output
+            .write_all(format!("{}{}", sep, value).as_bytes())
+            .expect("Failed to write results");
+    }
+    if let Some(phase) = sample.phase {
+        // the phase names are free text, make sure they do not break the columns
+        output
+            .write_all(format!("{}{}", sep, phase.replace(sep, "_")).as_bytes())
+            .expect("Failed to write results");
+    }
+    output.write_all(b"\n").expect("Failed to write results");
+}
+
+/// Writes the CSV header, `phase` adds the PHASE column. Returns the metrics of the
+/// columns in their order.
+pub fn print_header(
+    results: &HashMap<String, f64>,
+    sep: &str,
+    phase: bool,
+    output: &mut dyn Write,
+) -> Vec<String> {
+    output
+        .write_all(format!("Delta{}Time", sep).as_bytes())
+        .expect("Failed to write header");
+    let keys: Vec<String> = results.keys().sorted().cloned().collect();
+    for key in &keys {
+        output
+            .write_all(format!("{}{}", sep, key).as_bytes())
+            .expect("Failed to write header");
+    }
+    if phase {
+        output
+            .write_all(format!("{}PHASE", sep).as_bytes())
+            .expect("Failed to write header");
+    }
+    output.write_all(b"\n").expect("Failed to write header");
+    keys
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use flate2::write::GzEncoder;
+
+use super::{Sample, Sink};
+
+/// Output writer given to the sinks writing to a file.
+pub type Output = Box<dyn Write + Send>;
+
+/// When the output file is closed and the following samples written to a new one.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Rotation {
+    /// Once this number of bytes, before compression, is written to the file, e.g. `100MB`
+    Size(u64),
+    /// Once the file has been written for this duration, e.g. `1h`
+    Time(Duration),
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rotation::Size(bytes) => write!(f, "{}B", bytes),
+            Rotation::Time(duration) => write!(f, "{}s", duration.as_secs()),
+        }
+    }
+}
+
+impl From<Rotation> for String {
+    fn from(rotation: Rotation) -> Self {
+        rotation.to_string()
+    }
+}
+
+impl TryFrom<String> for Rotation {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (value, unit) = s.split_at(split);
+        let value: u64 = value
+            .parse()
+            .map_err(|_| format!("invalid rotation `{}`, e.g. 1h or 100MB", s))?;
+        let rotation = match unit.to_ascii_lowercase().as_str() {
+            "s" => Rotation::Time(Duration::from_secs(value)),
+            "m" => Rotation::Time(Duration::from_secs(value * 60)),
+            "h" => Rotation::Time(Duration::from_secs(value * 3600)),
+            "d" => Rotation::Time(Duration::from_secs(value * 86400)),
+            "b" => Rotation::Size(value),
+            "kb" => Rotation::Size(value << 10),
+            "mb" => Rotation::Size(value << 20),
+            "gb" => Rotation::Size(value << 30),
+            _ => {
+                return Err(format!(
+                    "invalid rotation unit `{}`, use s, m, h, d, B, KB, MB or GB",
+                    unit
+                ))
+            }
+        };
+        if value == 0 {
+            return Err("the rotation must be greater than zero".to_string());
+        }
+        Ok(rotation)
+    }
+}
+
+/// Creates the output file, compressed with gzip or zstd if its name ends with `.gz`
+/// or `.zst`. `written` counts the bytes written before the compression.
+pub fn create_output(path: &Path, written: Arc<AtomicU64>) -> io::Result<Output> {
+    let file = File::create(path)?;
+    let inner: Output = match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") => Box::new(GzEncoder::new(file, flate2::Compression::default())),
+        Some("zst") => Box::new(zstd::Encoder::new(file, 0)?.auto_finish()),
+        _ => Box::new(BufWriter::new(file)),
+    };
+    Ok(Box::new(CountingWriter { inner, written }))
+}
+
+struct CountingWriter {
+    inner: Output,
+    written: Arc<AtomicU64>,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written.fetch_add(written as u64, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the samples to a sequence of files, switching to a new file according to
+/// the rotation. Each file starts with its own header so that it can be read alone.
+///
+/// The files after the first one are numbered, e.g. `out.1.csv.gz` after `out.csv.gz`.
+pub struct RotatingSink {
+    open: Box<dyn Fn(Output) -> Box<dyn Sink>>,
+    path: PathBuf,
+    rotation: Rotation,
+    index: usize,
+    current: Box<dyn Sink>,
+    opened: Instant,
+    written: Arc<AtomicU64>,
+    header: HashMap<String, f64>,
+}
+
+impl RotatingSink {
+    /// `open` creates the sink writing to a new file.
+    pub fn new(
+        path: &Path,
+        rotation: Rotation,
+        open: Box<dyn Fn(Output) -> Box<dyn Sink>>,
+    ) -> io::Result<Self> {
+        let written = Arc::new(AtomicU64::new(0));
+        let current = open(create_output(path, written.clone())?);
+        Ok(RotatingSink {
+            open,
+            path: path.to_path_buf(),
+            rotation,
+            index: 0,
+            current,
+            opened: Instant::now(),
+            written,
+            header: HashMap::new(),
+        })
+    }
+
+    fn should_rotate(&self) -> bool {
+        match self.rotation {
+            Rotation::Size(bytes) => self.written.load(Ordering::Relaxed) >= bytes,
+            Rotation::Time(duration) => self.opened.elapsed() >= duration,
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.current.finish();
+        self.index += 1;
+        self.written.store(0, Ordering::Relaxed);
+        let output = create_output(&numbered_path(&self.path, self.index), self.written.clone())?;
+        // the previous file is only complete once its sink is dropped
+        self.current = (self.open)(output);
+        self.current.header(&self.header);
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+impl Sink for RotatingSink {
+    fn header(&mut self, results: &HashMap<String, f64>) {
+        self.header = results.clone();
+        self.current.header(results);
+    }
+
+    fn write(&mut self, sample: &Sample) {
+        if self.should_rotate() {
+            self.rotate().expect("Failed to open output file");
+        }
+        self.current.write(sample);
+    }
+
+    fn finish(&mut self) {
+        self.current.finish();
+    }
+}
+
+// Inserts the index of the file before its extensions, e.g. out.csv.gz -> out.2.csv.gz
+fn numbered_path(path: &Path, index: usize) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match name.split_once('.') {
+        Some((stem, extensions)) => format!("{}.{}.{}", stem, index, extensions),
+        None => format!("{}.{}", name, index),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,168 @@
+use itertools::Itertools;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use super::{Sample, Sink};
+use crate::sensor::{self, Sensor};
+
+// Interval between two writes to the HTTP endpoint, the samples taken in the meantime
+// are sent together.
+const POST_INTERVAL: Duration = Duration::from_secs(1);
+const POST_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum Destination {
+    Writer(Box<dyn Write>),
+    Http {
+        sender: Option<Sender<String>>,
+        thread: Option<JoinHandle<()>>,
+    },
+}
+
+/// Writes the samples in the InfluxDB line protocol, one measurement per sensor.
+///
+/// The lines are either written to a file or POSTed to a write endpoint, e.g.
+/// `http://localhost:8086/api/v2/write?bucket=energy&precision=ns`.
+pub struct InfluxSink {
+    destination: Destination,
+    run_id: String,
+}
+
+impl InfluxSink {
+    pub fn new(output: Box<dyn Write>, run_id: &str) -> Self {
+        InfluxSink {
+            destination: Destination::Writer(output),
+            run_id: run_id.to_string(),
+        }
+    }
+
+    /// Sends the samples to `url`, a background thread posts the lines of the samples
+    /// taken during each interval together.
+    pub fn http(url: &str, run_id: &str) -> Self {
+        let url = url.to_string();
+        let (sender, receiver) = channel::<String>();
+        let thread = thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new().timeout(POST_TIMEOUT).build();
+            let post = |batch: &mut String| {
+                if batch.is_empty() {
+                    return;
+                }
+                let response = agent
+                    .post(&url)
+                    .set("Content-Type", "text/plain; charset=utf-8")
+                    .send_string(batch);
+                if let Err(e) = response {
+                    eprintln!("[WARNING] Failed to write the metrics to {}: {}", url, e);
+                }
+                batch.clear();
+            };
+            let mut batch = String::new();
+            let mut deadline = Instant::now() + POST_INTERVAL;
+            loop {
+                match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(lines) => batch.push_str(&lines),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // the sink is finished
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if Instant::now() >= deadline {
+                    post(&mut batch);
+                    deadline = Instant::now() + POST_INTERVAL;
+                }
+            }
+            post(&mut batch);
+        });
+        InfluxSink {
+            destination: Destination::Http {
+                sender: Some(sender),
+                thread: Some(thread),
+            },
+            run_id: run_id.to_string(),
+        }
+    }
+
+    fn lines(&self, sample: &Sample) -> String {
+        let time = sample.time.duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let mut lines = String::new();
+        for key in sample.results.keys().sorted() {
+            // the line protocol has no representation for NaN and infinity
+            let mut fields = Vec::new();
+            let value = sample.results[key];
+            if value.is_finite() {
+                fields.push(("value", value));
+            }
+            if let Some(total) = sample.energy.get(key).filter(|total| total.is_finite()) {
+                fields.push(("joules", *total));
+            }
+            if fields.is_empty() {
+                continue;
+            }
+
+            let sensor = Sensor::parse(key);
+            let mut tags = vec![("run_id", self.run_id.clone())];
+            tags.extend(sensor.labels.iter().cloned());
+            if let Some(unit) = sensor::unit(key) {
+                tags.push(("unit", unit));
+            }
+            if let Some(phase) = sample.phase.filter(|phase| !phase.is_empty()) {
+                tags.push(("phase", phase.to_string()));
+            }
+            // the tags must be sorted by key for the best performance of InfluxDB
+            tags.sort_by(|a, b| a.0.cmp(b.0));
+
+            let _ = write!(lines, "{}", escape(&sensor.name.to_lowercase(), ", "));
+            for (tag, value) in tags {
+                let _ = write!(lines, ",{}={}", tag, escape(&value, ", ="));
+            }
+            let fields = fields
+                .iter()
+                .map(|(field, value)| format!("{}={}", field, value))
+                .join(",");
+            let _ = writeln!(lines, " {} {}", fields, time);
+        }
+        lines
+    }
+}
+
+impl Sink for InfluxSink {
+    fn write(&mut self, sample: &Sample) {
+        let lines = self.lines(sample);
+        match &mut self.destination {
+            Destination::Writer(output) => output
+                .write_all(lines.as_bytes())
+                .expect("Failed to write results"),
+            Destination::Http { sender, .. } => {
+                if let Some(sender) = sender {
+                    let _ = sender.send(lines);
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        match &mut self.destination {
+            Destination::Writer(output) => output.flush().expect("Failed to write results"),
+            Destination::Http { sender, thread } => {
+                // closing the channel stops the thread once the last batch is sent
+                sender.take();
+                if let Some(thread) = thread.take() {
+                    let _ = thread.join();
+                }
+            }
+        }
+    }
+}
+
+// Escapes the characters that delimit the elements of a line.
+fn escape(value: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
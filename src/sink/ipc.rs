@@ -0,0 +1,182 @@
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use super::file::Output;
+use super::{Sample, Sink};
+use crate::sensor;
+
+/// Writes the samples in the Arrow IPC stream format, one column per metric.
+///
+/// The schema is built from the metrics reported before the first sample, the unit of
+/// each column is stored in the `unit` metadata of its field and a metric missing from a
+/// sample is written as a null. The samples are written and flushed in record batches of
+/// `batch_size` samples: the stream has no footer, so the batches written before a crash
+/// can still be read.
+pub struct IpcSink {
+    output: Option<Output>,
+    writer: Option<StreamWriter<Output>>,
+    schema: SchemaRef,
+    run_id: String,
+    phase: bool,
+    batch_size: usize,
+    keys: Vec<String>,
+    delta: Vec<i64>,
+    time: Vec<i64>,
+    values: Vec<Vec<Option<f64>>>,
+    phases: Vec<String>,
+}
+
+impl IpcSink {
+    /// `phase` adds a column with the active phases.
+    pub fn new(output: Output, run_id: &str, phase: bool, batch_size: usize) -> Self {
+        IpcSink {
+            output: Some(output),
+            writer: None,
+            schema: Arc::new(Schema::empty()),
+            run_id: run_id.to_string(),
+            phase,
+            batch_size: batch_size.max(1),
+            keys: Vec::new(),
+            delta: Vec::new(),
+            time: Vec::new(),
+            values: Vec::new(),
+            phases: Vec::new(),
+        }
+    }
+
+    fn schema(&self) -> Schema {
+        let mut fields = vec![
+            Field::new("Delta", DataType::Int64, false),
+            Field::new(
+                "Time",
+                DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+                false,
+            ),
+        ];
+        for key in &self.keys {
+            let field = Field::new(sensor::base_name(key), DataType::Float64, true);
+            fields.push(match sensor::unit(key) {
+                Some(unit) => field.with_metadata(HashMap::from([("unit".to_string(), unit)])),
+                None => field,
+            });
+        }
+        if self.phase {
+            fields.push(Field::new("PHASE", DataType::Utf8, false));
+        }
+        let metadata = HashMap::from([("energibridge.run_id".to_string(), self.run_id.clone())]);
+        Schema::new(fields).with_metadata(metadata)
+    }
+
+    fn flush(&mut self) -> Result<(), arrow_schema::ArrowError> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        if self.time.is_empty() {
+            return Ok(());
+        }
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from(std::mem::take(&mut self.delta))),
+            Arc::new(TimestampMillisecondArray::from(std::mem::take(&mut self.time)).with_timezone("UTC")),
+        ];
+        for values in self.values.iter_mut() {
+            columns.push(Arc::new(Float64Array::from(std::mem::take(values))));
+        }
+        if self.phase {
+            columns.push(Arc::new(StringArray::from(std::mem::take(&mut self.phases))));
+        }
+        writer.write(&RecordBatch::try_new(self.schema.clone(), columns)?)?;
+        writer.flush()
+    }
+}
+
+impl Sink for IpcSink {
+    fn header(&mut self, results: &HashMap<String, f64>) {
+        self.keys = results.keys().sorted().cloned().collect();
+        self.values = vec![Vec::new(); self.keys.len()];
+        self.schema = Arc::new(self.schema());
+
+        let output = self.output.take().expect("Header already written");
+        let writer = StreamWriter::try_new(output, &self.schema).expect("Failed to write header");
+        self.writer = Some(writer);
+    }
+
+    fn write(&mut self, sample: &Sample) {
+        self.delta.push(sample.delta.as_millis() as i64);
+        self.time
+            .push(sample.time.duration_since(UNIX_EPOCH).unwrap().as_millis() as i64);
+        for (key, values) in self.keys.iter().zip(self.values.iter_mut()) {
+            // a metric that disappeared during the run is reported as missing
+            values.push(sample.results.get(key).copied());
+        }
+        if self.phase {
+            self.phases.push(sample.phase.unwrap_or_default().to_string());
+        }
+        if self.time.len() >= self.batch_size {
+            self.flush().expect("Failed to write results");
+        }
+    }
+
+    fn finish(&mut self) {
+        self.flush().expect("Failed to write results");
+        if let Some(writer) = self.writer.as_mut() {
+            writer.finish().expect("Failed to write results");
+            writer.flush().expect("Failed to write results");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+    use arrow_ipc::reader::StreamReader;
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn batches_are_readable_before_the_end_of_the_stream() {
+        let path = std::env::temp_dir().join(format!("energibridge-{}.arrows", std::process::id()));
+        let mut sink = IpcSink::new(Box::new(File::create(&path).unwrap()), "run", false, 2);
+        let full = HashMap::from([
+            ("PACKAGE_ENERGY (J)".to_string(), 12.5),
+            ("GPU0_USAGE".to_string(), 40.0),
+        ]);
+        let partial = HashMap::from([("PACKAGE_ENERGY (J)".to_string(), 13.0)]);
+        let energy = HashMap::new();
+        sink.header(&full);
+        for results in [&full, &partial, &full] {
+            sink.write(&Sample {
+                delta: Duration::from_millis(200),
+                time: SystemTime::now(),
+                results,
+                energy: &energy,
+                phase: None,
+            });
+        }
+        // the run is killed before finishing the stream, the last sample is lost
+        drop(sink);
+
+        let reader = StreamReader::try_new(File::open(&path).unwrap(), None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let schema = reader.schema();
+        let unit = |name: &str| schema.field_with_name(name).unwrap().metadata().get("unit").cloned();
+        assert_eq!(unit("PACKAGE_ENERGY").as_deref(), Some("J"));
+        assert_eq!(unit("GPU0_USAGE").as_deref(), Some("%"));
+        assert_eq!(unit("Time"), None);
+
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 1);
+        let column = |name: &str| {
+            let column = batches[0].column_by_name(name).unwrap();
+            column.as_any().downcast_ref::<Float64Array>().unwrap().clone()
+        };
+        assert_eq!(column("PACKAGE_ENERGY").values(), &[12.5, 13.0]);
+        let usage = column("GPU0_USAGE");
+        assert_eq!((usage.value(0), usage.is_null(1)), (40.0, true));
+    }
+}
@@ -0,0 +1,46 @@
+use serde_json::{json, Map, Value};
+use std::io::Write;
+use std::time::UNIX_EPOCH;
+
+use super::{Sample, Sink};
+use crate::sensor;
+
+/// Writes each sample as a JSON object on its own line.
+///
+/// The metrics are named without their unit suffix so that the samples of hosts
+/// reporting different columns can be concatenated.
+pub struct JsonlSink {
+    output: Box<dyn Write>,
+}
+
+impl JsonlSink {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        JsonlSink { output }
+    }
+}
+
+impl Sink for JsonlSink {
+    fn write(&mut self, sample: &Sample) {
+        let mut metrics = Map::new();
+        for (key, value) in sample.results {
+            metrics.insert(
+                sensor::base_name(key).to_string(),
+                json!({ "value": value, "unit": sensor::unit(key) }),
+            );
+        }
+        let mut object = json!({
+            "delta": sample.delta.as_millis() as u64,
+            "time": sample.time.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
+            "metrics": Value::Object(metrics),
+        });
+        if let Some(phase) = sample.phase {
+            object["phase"] = json!(phase);
+        }
+        serde_json::to_writer(&mut self.output, &object).expect("Failed to write results");
+        self.output.write_all(b"\n").expect("Failed to write results");
+    }
+
+    fn finish(&mut self) {
+        self.output.flush().expect("Failed to write results");
+    }
+}
@@ -0,0 +1,58 @@
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::UNIX_EPOCH;
+
+use super::{Sample, Sink};
+use crate::sensor;
+
+/// Writes the samples as CSV in the long (tidy) layout, one row per sample and metric.
+pub struct LongSink {
+    output: Box<dyn Write>,
+    sep: String,
+    phase: bool,
+}
+
+impl LongSink {
+    /// `phase` adds a column with the active phases.
+    pub fn new(output: Box<dyn Write>, sep: &str, phase: bool) -> Self {
+        LongSink {
+            output,
+            sep: sep.to_string(),
+            phase,
+        }
+    }
+}
+
+impl Sink for LongSink {
+    fn header(&mut self, _results: &HashMap<String, f64>) {
+        let sep = &self.sep;
+        let mut header = format!("Time{sep}Metric{sep}Value{sep}Unit");
+        if self.phase {
+            header.push_str(&format!("{sep}PHASE"));
+        }
+        writeln!(self.output, "{}", header).expect("Failed to write header");
+    }
+
+    fn write(&mut self, sample: &Sample) {
+        let sep = &self.sep;
+        let time = sample.time.duration_since(UNIX_EPOCH).unwrap().as_millis();
+        // the phase names are free text, make sure they do not break the columns
+        let phase = sample.phase.map(|phase| format!("{}{}", sep, phase.replace(sep, "_")));
+        for key in sample.results.keys().sorted() {
+            writeln!(
+                self.output,
+                "{time}{sep}{}{sep}{}{sep}{}{}",
+                sensor::base_name(key),
+                sample.results[key],
+                sensor::unit(key).unwrap_or_default(),
+                phase.as_deref().unwrap_or_default(),
+            )
+            .expect("Failed to write results");
+        }
+    }
+
+    fn finish(&mut self) {
+        self.output.flush().expect("Failed to write results");
+    }
+}
@@ -0,0 +1,63 @@
+pub mod csv;
+pub mod file;
+pub mod influx;
+pub mod ipc;
+pub mod jsonl;
+pub mod long;
+pub mod otlp;
+pub mod parquet;
+pub mod plot;
+pub mod report;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use crate::metadata::Metadata;
+
+/// Format of the measurements written to the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// One row per sample and one column per metric
+    #[default]
+    Csv,
+    /// InfluxDB line protocol, the output can be the URL of a write endpoint
+    Influx,
+    /// One JSON object per sample, with the unit of each metric
+    Jsonl,
+    /// One row per sample and metric with its unit
+    Long,
+    /// Parquet file with one column per metric, requires an output file
+    Parquet,
+    /// Arrow IPC stream with one column per metric, readable after a crash, requires an
+    /// output file
+    Arrow,
+}
+
+/// A sample of the metrics, as given to the sinks.
+pub struct Sample<'a> {
+    /// Time elapsed since the previous sample.
+    pub delta: Duration,
+    pub time: SystemTime,
+    pub results: &'a HashMap<String, f64>,
+    /// Energy in joules consumed by each domain since the beginning of the run.
+    pub energy: &'a HashMap<String, f64>,
+    /// Active phases, only set when the phases are recorded.
+    pub phase: Option<&'a str>,
+}
+
+/// Destination of the samples collected during a run.
+pub trait Sink {
+    /// Called once before the first sample with the metrics that will be reported.
+    fn header(&mut self, _results: &HashMap<String, f64>) {}
+
+    fn write(&mut self, sample: &Sample);
+
+    /// Called once after the last sample with the description of the run.
+    fn metadata(&mut self, _metadata: &Metadata) {}
+
+    /// Called once after the last sample.
+    fn finish(&mut self) {}
+}
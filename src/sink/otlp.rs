@@ -0,0 +1,449 @@
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use prost::Message;
+
+use super::{Sample, Sink};
+use crate::energy::{is_energy, power_factor};
+use crate::sensor::{self, Sensor};
+
+// Longest time an export may take, including connecting to the collector.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+// Longest time the end of the run waits for the pending batches to be exported.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pushes the samples as OTLP metrics over HTTP/protobuf.
+///
+/// The data points are batched and sent by a background thread so that a slow
+/// collector does not delay the sampling.
+pub struct OtlpSink {
+    resource: Resource,
+    start: u64,
+    batch_size: usize,
+    metrics: Vec<Metric>,
+    samples: usize,
+    sender: Option<Sender<ExportMetricsServiceRequest>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl OtlpSink {
+    /// `attributes` describe the run (host, run id, command) and are attached to
+    /// every batch as resource attributes.
+    pub fn new(
+        endpoint: &str,
+        batch_size: usize,
+        attributes: Vec<(&str, String)>,
+        start: SystemTime,
+    ) -> Self {
+        let endpoint = endpoint.to_string();
+        let (sender, receiver) = channel::<ExportMetricsServiceRequest>();
+        let thread = thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new().timeout(EXPORT_TIMEOUT).build();
+            for request in receiver {
+                let response = agent
+                    .post(&endpoint)
+                    .set("Content-Type", "application/x-protobuf")
+                    .send_bytes(&request.encode_to_vec());
+                if let Err(e) = response {
+                    eprintln!(
+                        "[WARNING] Failed to export the metrics to {}: {}",
+                        endpoint, e
+                    );
+                }
+            }
+        });
+        let mut resource_attributes = vec![key_value("service.name", "energibridge".to_string())];
+        resource_attributes.extend(
+            attributes
+                .into_iter()
+                .map(|(key, value)| key_value(key, value)),
+        );
+        OtlpSink {
+            resource: Resource {
+                attributes: resource_attributes,
+                dropped_attributes_count: 0,
+            },
+            start: unix_nanos(start),
+            batch_size: batch_size.max(1),
+            metrics: Vec::new(),
+            samples: 0,
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    fn add(&mut self, name: String, unit: &str, data: metric::Data) {
+        // the data points of the batch are grouped by metric
+        match self
+            .metrics
+            .iter_mut()
+            .find(|m| m.name == name && m.unit == unit)
+        {
+            Some(metric) => match (&mut metric.data, data) {
+                (Some(metric::Data::Gauge(gauge)), metric::Data::Gauge(other)) => {
+                    gauge.data_points.extend(other.data_points)
+                }
+                (Some(metric::Data::Sum(sum)), metric::Data::Sum(other)) => {
+                    sum.data_points.extend(other.data_points)
+                }
+                _ => {}
+            },
+            None => self.metrics.push(Metric {
+                name,
+                description: String::new(),
+                unit: unit.to_string(),
+                data: Some(data),
+            }),
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.metrics.is_empty() {
+            return;
+        }
+        let request = ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(self.resource.clone()),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: "energibridge".to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                    }),
+                    metrics: std::mem::take(&mut self.metrics),
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        };
+        self.samples = 0;
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(request);
+        }
+    }
+}
+
+impl Sink for OtlpSink {
+    fn write(&mut self, sample: &Sample) {
+        let (start, time) = (self.start, unix_nanos(sample.time));
+        for (key, value) in sample.results {
+            let sensor = Sensor::parse(key);
+            let attributes: Vec<KeyValue> = sensor
+                .labels
+                .iter()
+                .map(|(label, index)| key_value(label, index.clone()))
+                .collect();
+            let name = format!("energibridge.{}", sensor.name.to_lowercase());
+            let gauge = |value| {
+                metric::Data::Gauge(Gauge {
+                    data_points: vec![data_point(attributes.clone(), 0, time, value)],
+                })
+            };
+            let counter = |value| {
+                metric::Data::Sum(Sum {
+                    data_points: vec![data_point(attributes.clone(), start, time, value)],
+                    aggregation_temporality: AGGREGATION_TEMPORALITY_CUMULATIVE,
+                    is_monotonic: true,
+                })
+            };
+
+            if is_energy(key) {
+                if let Some(total) = sample.energy.get(key) {
+                    let data = counter(*total);
+                    self.add(name, "J", data);
+                }
+            } else if let Some(factor) = power_factor(key) {
+                let data = gauge(value * factor);
+                self.add(name.clone(), "W", data);
+                if let Some(total) = sample.energy.get(key) {
+                    let data = counter(*total);
+                    self.add(name.replace("_power", "_energy"), "J", data);
+                }
+            } else {
+                let unit = sensor::unit(key).map(|u| ucum_unit(&u)).unwrap_or_default();
+                let data = gauge(*value);
+                self.add(name, &unit, data);
+            }
+        }
+
+        self.samples += 1;
+        if self.samples >= self.batch_size {
+            self.flush();
+        }
+    }
+
+    fn finish(&mut self) {
+        self.flush();
+        // closing the channel stops the thread once the pending batches are sent
+        self.sender.take();
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        let deadline = Instant::now() + FINISH_TIMEOUT;
+        while !thread.is_finished() {
+            if Instant::now() >= deadline {
+                // the thread is detached, the batches it still holds are lost
+                eprintln!(
+                    "[WARNING] Timed out exporting the metrics, the last batches are dropped"
+                );
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = thread.join();
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64
+}
+
+// Units of the metrics in the UCUM notation used by OpenTelemetry.
+fn ucum_unit(unit: &str) -> String {
+    match unit {
+        "MHZ" | "MHz" => "MHz",
+        "bytes" => "By",
+        "MiB" => "MiBy",
+        "°C" => "Cel",
+        unit => unit,
+    }
+    .to_string()
+}
+
+fn key_value(key: &str, value: String) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value)),
+        }),
+    }
+}
+
+fn data_point(attributes: Vec<KeyValue>, start: u64, time: u64, value: f64) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes,
+        start_time_unix_nano: start,
+        time_unix_nano: time,
+        value: Some(number_data_point::Value::AsDouble(value)),
+    }
+}
+
+// Subset of the OTLP metrics protocol (opentelemetry/proto/collector/metrics/v1) used
+// by EnergiBridge, the field numbers match the upstream definitions.
+
+const AGGREGATION_TEMPORALITY_CUMULATIVE: i32 = 2;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ExportMetricsServiceRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ResourceMetrics {
+    #[prost(message, optional, tag = "1")]
+    pub resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    pub scope_metrics: Vec<ScopeMetrics>,
+    #[prost(string, tag = "3")]
+    pub schema_url: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Resource {
+    #[prost(message, repeated, tag = "1")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(uint32, tag = "2")]
+    pub dropped_attributes_count: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ScopeMetrics {
+    #[prost(message, optional, tag = "1")]
+    pub scope: Option<InstrumentationScope>,
+    #[prost(message, repeated, tag = "2")]
+    pub metrics: Vec<Metric>,
+    #[prost(string, tag = "3")]
+    pub schema_url: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct InstrumentationScope {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub version: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Metric {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub description: String,
+    #[prost(string, tag = "3")]
+    pub unit: String,
+    #[prost(oneof = "metric::Data", tags = "5, 7")]
+    pub data: Option<metric::Data>,
+}
+
+pub mod metric {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Data {
+        #[prost(message, tag = "5")]
+        Gauge(super::Gauge),
+        #[prost(message, tag = "7")]
+        Sum(super::Sum),
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Gauge {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Sum {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<NumberDataPoint>,
+    #[prost(int32, tag = "2")]
+    pub aggregation_temporality: i32,
+    #[prost(bool, tag = "3")]
+    pub is_monotonic: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct NumberDataPoint {
+    #[prost(message, repeated, tag = "7")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(fixed64, tag = "2")]
+    pub start_time_unix_nano: u64,
+    #[prost(fixed64, tag = "3")]
+    pub time_unix_nano: u64,
+    #[prost(oneof = "number_data_point::Value", tags = "4")]
+    pub value: Option<number_data_point::Value>,
+}
+
+pub mod number_data_point {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(double, tag = "4")]
+        AsDouble(f64),
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct KeyValue {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AnyValue {
+    #[prost(oneof = "any_value::Value", tags = "1")]
+    pub value: Option<any_value::Value>,
+}
+
+pub mod any_value {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(string, tag = "1")]
+        StringValue(String),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn exports_to_collector() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/v1/metrics", server.server_addr());
+        let collector = thread::spawn(move || {
+            let mut request = server
+                .recv_timeout(Duration::from_secs(10))
+                .unwrap()
+                .unwrap();
+            let mut body = Vec::new();
+            request.as_reader().read_to_end(&mut body).unwrap();
+            let content_type = request
+                .headers()
+                .iter()
+                .find(|h| h.field.equiv("Content-Type"))
+                .map(|h| h.value.to_string());
+            let path = request.url().to_string();
+            request.respond(tiny_http::Response::empty(200)).unwrap();
+            (path, content_type, body)
+        });
+
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut sink = OtlpSink::new(
+            &endpoint,
+            10,
+            vec![("energibridge.run_id", "run".into())],
+            start,
+        );
+        let results = HashMap::from([
+            ("PACKAGE_ENERGY (J)".to_string(), 1234.5),
+            ("CPU_USAGE_0".to_string(), 42.0),
+        ]);
+        let energy = HashMap::from([("PACKAGE_ENERGY (J)".to_string(), 3.5)]);
+        sink.write(&Sample {
+            delta: Duration::from_millis(200),
+            time: start + Duration::from_secs(1),
+            results: &results,
+            energy: &energy,
+            phase: None,
+        });
+        sink.finish();
+
+        let (path, content_type, body) = collector.join().unwrap();
+        assert_eq!(path, "/v1/metrics");
+        assert_eq!(content_type.as_deref(), Some("application/x-protobuf"));
+        let request = ExportMetricsServiceRequest::decode(body.as_slice()).unwrap();
+        let resource = &request.resource_metrics[0];
+        let attributes: Vec<&str> = resource
+            .resource
+            .as_ref()
+            .unwrap()
+            .attributes
+            .iter()
+            .map(|a| a.key.as_str())
+            .collect();
+        assert_eq!(attributes, ["service.name", "energibridge.run_id"]);
+        let metrics = &resource.scope_metrics[0].metrics;
+        assert_eq!(metrics.len(), 2);
+
+        let energy = metrics
+            .iter()
+            .find(|m| m.name == "energibridge.package_energy")
+            .unwrap();
+        assert_eq!(energy.unit, "J");
+        let Some(metric::Data::Sum(sum)) = &energy.data else {
+            panic!("the energy is not a sum: {:?}", energy.data);
+        };
+        assert!(sum.is_monotonic);
+        let point = &sum.data_points[0];
+        assert_eq!(point.value, Some(number_data_point::Value::AsDouble(3.5)));
+        assert_eq!(point.start_time_unix_nano, 1_700_000_000_000_000_000);
+        assert_eq!(point.time_unix_nano, 1_700_000_001_000_000_000);
+
+        let usage = metrics
+            .iter()
+            .find(|m| m.name == "energibridge.cpu_usage")
+            .unwrap();
+        let Some(metric::Data::Gauge(gauge)) = &usage.data else {
+            panic!("the usage is not a gauge: {:?}", usage.data);
+        };
+        assert_eq!(
+            gauge.data_points[0].value,
+            Some(number_data_point::Value::AsDouble(42.0))
+        );
+        assert_eq!(gauge.data_points[0].attributes[0].key, "core");
+    }
+}
@@ -0,0 +1,249 @@
+use arrow_schema::{Field, Schema};
+use itertools::Itertools;
+use parquet::arrow::{add_encoded_arrow_schema_to_metadata, parquet_to_arrow_schema};
+use parquet::basic::{Compression, LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::{SchemaDescriptor, Type};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use super::file::Output;
+use super::{Sample, Sink};
+use crate::sensor;
+
+/// Writes the samples to a Parquet file, one column per metric.
+///
+/// The schema is built from the metrics reported before the first sample, the unit
+/// of each column is stored in the `unit` metadata of its field in the Arrow schema
+/// embedded in the file. A metric missing from a sample is written as a null.
+/// The rows are written in row groups of `row_group_size` samples during the run so
+/// that only the last group is kept in memory, the file can only be read once it is
+/// closed with its footer at the end of the run. `IpcSink` writes a stream that can be
+/// read after a crash.
+pub struct ParquetSink {
+    output: Option<Output>,
+    writer: Option<SerializedFileWriter<Output>>,
+    run_id: String,
+    phase: bool,
+    row_group_size: usize,
+    keys: Vec<String>,
+    delta: Vec<i64>,
+    time: Vec<i64>,
+    values: Vec<Vec<Option<f64>>>,
+    phases: Vec<ByteArray>,
+}
+
+impl ParquetSink {
+    /// `phase` adds a column with the active phases.
+    pub fn new(output: Output, run_id: &str, phase: bool, row_group_size: usize) -> Self {
+        ParquetSink {
+            output: Some(output),
+            writer: None,
+            run_id: run_id.to_string(),
+            phase,
+            row_group_size: row_group_size.max(1),
+            keys: Vec::new(),
+            delta: Vec::new(),
+            time: Vec::new(),
+            values: Vec::new(),
+            phases: Vec::new(),
+        }
+    }
+
+    fn schema(&self) -> Type {
+        let int64 = |name: &str, logical_type| {
+            Type::primitive_type_builder(name, PhysicalType::INT64)
+                .with_repetition(Repetition::REQUIRED)
+                .with_logical_type(logical_type)
+                .build()
+                .unwrap()
+        };
+        let mut fields = vec![
+            Arc::new(int64("Delta", None)),
+            Arc::new(int64("Time", Some(LogicalType::timestamp(true, TimeUnit::MILLIS)))),
+        ];
+        for key in &self.keys {
+            let field = Type::primitive_type_builder(sensor::base_name(key), PhysicalType::DOUBLE)
+                .with_repetition(Repetition::OPTIONAL)
+                .build()
+                .unwrap();
+            fields.push(Arc::new(field));
+        }
+        if self.phase {
+            let field = Type::primitive_type_builder("PHASE", PhysicalType::BYTE_ARRAY)
+                .with_repetition(Repetition::REQUIRED)
+                .with_logical_type(Some(LogicalType::String))
+                .build()
+                .unwrap();
+            fields.push(Arc::new(field));
+        }
+        Type::group_type_builder("energibridge")
+            .with_fields(fields)
+            .build()
+            .unwrap()
+    }
+
+    // The Arrow schema matching the Parquet schema, the readers based on Arrow (pyarrow,
+    // polars, DuckDB) expose the metadata of its fields.
+    fn arrow_schema(&self, schema: &Arc<Type>) -> Schema {
+        let descriptor = SchemaDescriptor::new(schema.clone());
+        let arrow = parquet_to_arrow_schema(&descriptor, None).expect("Invalid schema");
+        let units: HashMap<&str, String> = self
+            .keys
+            .iter()
+            .filter_map(|key| Some((sensor::base_name(key), sensor::unit(key)?)))
+            .collect();
+        let fields: Vec<Field> = arrow
+            .fields()
+            .iter()
+            .map(|field| match units.get(field.name().as_str()) {
+                Some(unit) => field
+                    .as_ref()
+                    .clone()
+                    .with_metadata(HashMap::from([("unit".to_string(), unit.clone())])),
+                None => field.as_ref().clone(),
+            })
+            .collect();
+        Schema::new(fields)
+    }
+
+    fn flush(&mut self) -> parquet::errors::Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        if self.time.is_empty() {
+            return Ok(());
+        }
+        let mut group = writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = group.next_column()? {
+            match index {
+                0 => column.typed::<Int64Type>().write_batch(&self.delta, None, None)?,
+                1 => column.typed::<Int64Type>().write_batch(&self.time, None, None)?,
+                i if i - 2 < self.values.len() => {
+                    let values = &self.values[i - 2];
+                    let present: Vec<f64> = values.iter().flatten().copied().collect();
+                    let levels: Vec<i16> = values.iter().map(|v| v.is_some() as i16).collect();
+                    column
+                        .typed::<DoubleType>()
+                        .write_batch(&present, Some(&levels), None)?
+                }
+                _ => column.typed::<ByteArrayType>().write_batch(&self.phases, None, None)?,
+            };
+            column.close()?;
+            index += 1;
+        }
+        group.close()?;
+        writer.flush()?;
+
+        self.delta.clear();
+        self.time.clear();
+        self.values.iter_mut().for_each(Vec::clear);
+        self.phases.clear();
+        Ok(())
+    }
+}
+
+impl Sink for ParquetSink {
+    fn header(&mut self, results: &HashMap<String, f64>) {
+        self.keys = results.keys().sorted().cloned().collect();
+        self.values = vec![Vec::new(); self.keys.len()];
+
+        let schema = Arc::new(self.schema());
+        let metadata = vec![KeyValue::new(
+            "energibridge.run_id".to_string(),
+            self.run_id.clone(),
+        )];
+        let mut properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_key_value_metadata(Some(metadata))
+            .build();
+        add_encoded_arrow_schema_to_metadata(&self.arrow_schema(&schema), &mut properties);
+        let output = self.output.take().expect("Header already written");
+        let writer = SerializedFileWriter::new(output, schema, Arc::new(properties))
+            .expect("Failed to write header");
+        self.writer = Some(writer);
+    }
+
+    fn write(&mut self, sample: &Sample) {
+        self.delta.push(sample.delta.as_millis() as i64);
+        self.time
+            .push(sample.time.duration_since(UNIX_EPOCH).unwrap().as_millis() as i64);
+        for (key, values) in self.keys.iter().zip(self.values.iter_mut()) {
+            // a metric that disappeared during the run is reported as missing
+            values.push(sample.results.get(key).copied());
+        }
+        if self.phase {
+            self.phases.push(sample.phase.unwrap_or_default().into());
+        }
+        if self.time.len() >= self.row_group_size {
+            self.flush().expect("Failed to write results");
+        }
+    }
+
+    fn finish(&mut self) {
+        self.flush().expect("Failed to write results");
+        if let Some(writer) = self.writer.take() {
+            writer.close().expect("Failed to write results");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field as Value;
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn units_and_missing_values() {
+        let path =
+            std::env::temp_dir().join(format!("energibridge-{}.parquet", std::process::id()));
+        let mut sink = ParquetSink::new(Box::new(File::create(&path).unwrap()), "run", false, 1);
+        let full = HashMap::from([
+            ("PACKAGE_ENERGY (J)".to_string(), 12.5),
+            ("GPU0_USAGE".to_string(), 40.0),
+        ]);
+        let partial = HashMap::from([("PACKAGE_ENERGY (J)".to_string(), 13.0)]);
+        let energy = HashMap::new();
+        sink.header(&full);
+        for results in [&full, &partial] {
+            sink.write(&Sample {
+                delta: Duration::from_millis(200),
+                time: SystemTime::now(),
+                results,
+                energy: &energy,
+                phase: None,
+            });
+        }
+        sink.finish();
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        let schema = parquet_to_arrow_schema(metadata.schema_descr(), metadata.key_value_metadata())
+            .unwrap();
+        let unit = |name: &str| {
+            let field = schema.field_with_name(name).unwrap();
+            field.metadata().get("unit").cloned()
+        };
+        assert_eq!(unit("PACKAGE_ENERGY").as_deref(), Some("J"));
+        assert_eq!(unit("GPU0_USAGE").as_deref(), Some("%"));
+        assert_eq!(unit("Time"), None);
+
+        let rows: Vec<Vec<Value>> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().into_columns().into_iter().map(|(_, v)| v).collect())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][2..], [Value::Double(40.0), Value::Double(12.5)]);
+        assert_eq!(rows[1][2..], [Value::Null, Value::Double(13.0)]);
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use super::{Sample, Sink};
+use crate::plot::Chart;
+
+/// Draws a chart of the run once it is over.
+pub struct PlotSink {
+    path: PathBuf,
+    chart: Chart,
+}
+
+impl PlotSink {
+    pub fn new(path: &str) -> Self {
+        PlotSink {
+            path: PathBuf::from(path),
+            chart: Chart::new(),
+        }
+    }
+}
+
+impl Sink for PlotSink {
+    fn write(&mut self, sample: &Sample) {
+        let time = sample.time.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        self.chart
+            .add(time, sample.results, sample.energy, sample.phase);
+    }
+
+    fn finish(&mut self) {
+        if let Err(e) = self.chart.save(&self.path) {
+            eprintln!("[WARNING] Failed to plot {}: {}", self.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::csv::{print_header, print_results};
+use super::{Sample, Sink};
+use crate::metadata::Metadata;
+use crate::plot::Chart;
+use crate::summary::Summary;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+td,th{border:1px solid #ccc;padding:4px 8px;text-align:left}\
+th{background:#f4f4f4}td.number{text-align:right}\
+pre{background:#f8f8f8;padding:1em;overflow:auto;max-height:30em}";
+
+/// Writes a self-contained HTML page describing the run once it is over: the
+/// metadata, the energy consumed by each domain, the charts and the raw samples.
+pub struct ReportSink {
+    path: PathBuf,
+    phase: bool,
+    data: Vec<u8>,
+    // metrics of the columns of the raw samples
+    keys: Vec<String>,
+    chart: Chart,
+    summary: Summary,
+    previous: Option<(SystemTime, HashMap<String, f64>)>,
+    metadata: Vec<(String, String)>,
+}
+
+impl ReportSink {
+    /// `phase` adds the active phases to the raw samples.
+    pub fn new(path: &str, phase: bool) -> Self {
+        ReportSink {
+            path: PathBuf::from(path),
+            phase,
+            data: Vec::new(),
+            keys: Vec::new(),
+            chart: Chart::new(),
+            summary: Summary::new(),
+            previous: None,
+            metadata: Vec::new(),
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>EnergiBridge report</title>\n<style>{}</style>\n</head>\n<body>\n\
+             <h1>EnergiBridge report</h1>\n",
+            STYLE
+        );
+
+        html.push_str("<h2>Run</h2>\n<table>\n");
+        for (key, value) in &self.metadata {
+            // the nested values, such as the configuration, are pretty-printed
+            let value = if value.contains('\n') {
+                format!("<pre>{}</pre>", escape(value))
+            } else {
+                escape(value)
+            };
+            let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", escape(key), value);
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Energy consumption</h2>\n");
+        let _ = writeln!(
+            html,
+            "<p>{:.3} sec of measurements.</p>",
+            self.summary.duration().as_secs_f64()
+        );
+        html.push_str(
+            "<table>\n<tr><th>Domain</th><th>Energy (J)</th><th>Average power (W)</th>\
+             <th>Min power (W)</th><th>Max power (W)</th></tr>\n",
+        );
+        for (key, stats) in self.summary.domains() {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td class=\"number\">{:.3}</td><td class=\"number\">{:.3}</td>\
+                 <td class=\"number\">{:.3}</td><td class=\"number\">{:.3}</td></tr>",
+                escape(key),
+                stats.energy,
+                stats.average_power(),
+                stats.min_power,
+                stats.max_power
+            );
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Charts</h2>\n");
+        match self.chart.to_svg() {
+            Ok(svg) => html.push_str(&svg),
+            Err(e) => {
+                let _ = writeln!(html, "<p>{}</p>", escape(&e.to_string()));
+            }
+        }
+
+        let _ = write!(
+            html,
+            "\n<h2>Raw data</h2>\n<details>\n<summary>{} samples (CSV)</summary>\n<pre>{}</pre>\n\
+             </details>\n</body>\n</html>\n",
+            self.data.iter().filter(|b| **b == b'\n').count().saturating_sub(1),
+            escape(&String::from_utf8_lossy(&self.data))
+        );
+        html
+    }
+}
+
+impl Sink for ReportSink {
+    fn header(&mut self, results: &HashMap<String, f64>) {
+        self.keys = print_header(results, ",", self.phase, &mut self.data);
+    }
+
+    fn write(&mut self, sample: &Sample) {
+        print_results(sample, &self.keys, ",", &mut self.data);
+        let time = sample.time.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        self.chart
+            .add(time, sample.results, sample.energy, sample.phase);
+
+        if let Some((previous_time, previous)) = &self.previous {
+            let elapsed = sample.time.duration_since(*previous_time).unwrap_or_default();
+            let deltas = sample
+                .energy
+                .iter()
+                .map(|(key, total)| (key.clone(), total - previous.get(key).unwrap_or(&0.0)))
+                .collect();
+            self.summary.update(&deltas, elapsed);
+        }
+        self.previous = Some((sample.time, sample.energy.clone()));
+    }
+
+    fn metadata(&mut self, metadata: &Metadata) {
+        self.metadata = metadata
+            .entries()
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(value) => value.clone(),
+                    Value::Object(_) => serde_json::to_string_pretty(value).unwrap(),
+                    value => value.to_string(),
+                };
+                (key.clone(), value)
+            })
+            .collect();
+    }
+
+    fn finish(&mut self) {
+        if let Err(e) = std::fs::write(&self.path, self.render()) {
+            eprintln!("[WARNING] Failed to write the report {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use crate::phase::PhaseStats;
+
+#[derive(Debug, Default, Clone)]
+pub struct DomainStats {
+    /// Energy in joules.
+    pub energy: f64,
+    /// Lowest and highest power over an interval, in Watts.
+    pub min_power: f64,
+    pub max_power: f64,
+    intervals: u32,
+    // start of the first interval and end of the last one the domain reported, since
+    // the beginning of the summary
+    first: Option<Duration>,
+    last: Duration,
+}
+
+impl DomainStats {
+    /// Average power over the intervals from the first to the last one the domain
+    /// reported, in Watts.
+    pub fn average_power(&self) -> f64 {
+        let seconds = self
+            .last
+            .saturating_sub(self.first.unwrap_or_default())
+            .as_secs_f64();
+        if seconds > 0.0 {
+            self.energy / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Energy consumed by each domain over a run and the range of its power draw.
+///
+/// Built from the deltas of an `EnergyAccumulator`, both during a run and when
+/// analyzing a recorded output, so that they report the same figures.
+#[derive(Debug, Default)]
+pub struct Summary {
+    duration: Duration,
+    domains: BTreeMap<String, DomainStats>,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the energy consumed by each domain during an interval of `elapsed`.
+    pub fn update(&mut self, deltas: &HashMap<String, f64>, elapsed: Duration) {
+        let start = self.duration;
+        self.duration += elapsed;
+        for (key, energy) in deltas {
+            let stats = self.domains.entry(key.clone()).or_default();
+            stats.energy += energy;
+            // a domain that stops reporting is averaged over the time it reported
+            stats.first.get_or_insert(start);
+            stats.last = self.duration;
+            if elapsed.is_zero() {
+                continue;
+            }
+            let power = energy / elapsed.as_secs_f64();
+            if stats.intervals == 0 {
+                (stats.min_power, stats.max_power) = (power, power);
+            } else {
+                stats.min_power = stats.min_power.min(power);
+                stats.max_power = stats.max_power.max(power);
+            }
+            stats.intervals += 1;
+        }
+    }
+
+    /// Energy in joules consumed by a domain.
+    pub fn energy(&self, key: &str) -> Option<f64> {
+        self.domains.get(key).map(|stats| stats.energy)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn domains(&self) -> &BTreeMap<String, DomainStats> {
+        &self.domains
+    }
+
+    /// Prints the energy consumed by `primary` during the `duration` of the execution,
+    /// followed by the statistics of every domain if `domains` is set.
+    pub fn print(&self, primary: Option<&str>, duration: Duration, domains: bool) {
+        let energy = primary.and_then(|key| self.energy(key)).unwrap_or(0.0);
+        if energy > 0.0 {
+            println!(
+                "Energy consumption in joules: {} for {} sec of execution.",
+                energy,
+                duration.as_secs_f32()
+            );
+        }
+        if !domains {
+            return;
+        }
+        for (key, stats) in &self.domains {
+            println!(
+                "{}: {:.3} joules, {:.3} W on average (min {:.3} W, max {:.3} W)",
+                key,
+                stats.energy,
+                stats.average_power(),
+                stats.min_power,
+                stats.max_power
+            );
+        }
+    }
+}
+
+/// Prints the energy consumed during each phase.
+pub fn print_phases(phases: &[(String, PhaseStats)]) {
+    for (name, stats) in phases {
+        println!(
+            "Phase {}: {} joules for {} sec of execution ({} occurrences).",
+            name,
+            stats.energy,
+            stats.duration.as_secs_f32(),
+            stats.count
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average_over_the_reported_intervals() {
+        let mut summary = Summary::new();
+        let second = Duration::from_secs(1);
+        let both = HashMap::from([
+            ("PACKAGE_ENERGY (J)".to_string(), 20.0),
+            ("GPU0_POWER (mWatts)".to_string(), 100.0),
+        ]);
+        let package = HashMap::from([("PACKAGE_ENERGY (J)".to_string(), 20.0)]);
+        for deltas in [&package, &both, &both, &package] {
+            summary.update(deltas, second);
+        }
+        let gpu = &summary.domains()["GPU0_POWER (mWatts)"];
+        assert_eq!((gpu.energy, gpu.average_power()), (200.0, 100.0));
+        assert_eq!((gpu.min_power, gpu.max_power), (100.0, 100.0));
+        assert_eq!(summary.domains()["PACKAGE_ENERGY (J)"].average_power(), 20.0);
+    }
+}
@@ -0,0 +1,41 @@
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+// Largest value (in joules) each energy counter can hold before it wraps around to zero.
+// The CPU backends register them since only they know the width and unit of the registers.
+static COUNTER_RANGES: Lazy<Mutex<HashMap<String, f64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn set_counter_range(key: &str, joules: f64) {
+    COUNTER_RANGES
+        .lock()
+        .unwrap()
+        .insert(key.to_string(), joules);
+}
+
+/// The ranges registered so far, saved in the metadata of the run.
+pub fn counter_ranges() -> BTreeMap<String, f64> {
+    COUNTER_RANGES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(key, range)| (key.clone(), *range))
+        .collect()
+}
+
+fn counter_range(key: &str) -> Option<f64> {
+    COUNTER_RANGES.lock().unwrap().get(key).copied()
+}
+
+/// The energy counted between two readings of a counter.
+pub fn counter_delta(key: &str, previous: f64, current: f64) -> f64 {
+    if current >= previous {
+        return current - previous;
+    }
+    // the counter wrapped around, if its range is unknown the best we can do is
+    // to assume it restarted from zero
+    match counter_range(key) {
+        Some(range) => range - previous + current,
+        None => current,
+    }
+}
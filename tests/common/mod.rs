@@ -0,0 +1,75 @@
+// Each test crate uses a part of the helpers.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A file of the test data directory.
+pub fn data(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(name)
+}
+
+/// A path for an output of the test, removed if it already exists.
+pub fn output(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("energibridge-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Runs EnergiBridge and returns its standard output, panics if it fails.
+pub fn energibridge(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_energibridge"))
+        .args(args)
+        .output()
+        .expect("failed to run energibridge");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        output.status.success(),
+        "energibridge {:?} failed\n{}{}",
+        args,
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    stdout
+}
+
+/// The header and the rows of a CSV output, the empty fields are None.
+pub fn read_csv(path: &Path) -> (Vec<String>, Vec<Vec<Option<f64>>>) {
+    let content = std::fs::read_to_string(path).expect("failed to read the output");
+    let mut lines = content.lines();
+    let header = lines.next().unwrap().split(',').map(String::from).collect();
+    let rows = lines
+        .map(|line| line.split(',').map(|field| field.parse().ok()).collect())
+        .collect();
+    (header, rows)
+}
+
+/// The value of a column in a row.
+pub fn column(header: &[String], row: &[Option<f64>], name: &str) -> Option<f64> {
+    let index = header.iter().position(|c| c == name).unwrap_or_else(|| {
+        panic!("no column {} in {:?}", name, header);
+    });
+    row[index]
+}
+
+/// The energy in joules and the duration in seconds of the legacy summary line.
+pub fn summary(stdout: &str) -> (f64, f64) {
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Energy consumption in joules: "))
+        .and_then(|rest| {
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            Some((fields.first()?.parse().ok()?, fields.get(2)?.parse().ok()?))
+        })
+        .unwrap_or_else(|| panic!("no summary in {}", stdout))
+}
+
+/// The energy in joules of a domain in the summary printed with --summary-domains.
+pub fn domain_energy(stdout: &str, key: &str) -> f64 {
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{}: ", key)))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|energy| energy.parse().ok())
+        .unwrap_or_else(|| panic!("no {} in the summary {}", key, stdout))
+}
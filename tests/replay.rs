@@ -0,0 +1,84 @@
+//! Replays checked-in MSR recordings and checks the energy computed by the CPU backends.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use common::{column, data, domain_energy, energibridge, output, read_csv, summary};
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() <= 1e-9 * expected.abs().max(1.0),
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn intel_skylake_sp() {
+    let recording = data("skylake-sp.msr.csv");
+    let csv = output("skylake-sp.csv");
+    let stdout = energibridge(&[
+        "--replay-msr",
+        recording.to_str().unwrap(),
+        "--metrics",
+        "*_ENERGY*",
+        "--summary-domains",
+        "--interval",
+        "200",
+        "--output",
+        csv.to_str().unwrap(),
+        "--",
+        "sleep",
+        "0.5",
+    ]);
+
+    // MSR_RAPL_POWER_UNIT 0xA0E03: 2^-14 J, and the fixed 15.3 µJ of the server DRAM
+    let unit = 0.5f64.powi(14);
+    let (header, rows) = read_csv(&csv);
+    let last = rows.last().unwrap();
+    assert_close(column(&header, last, "PACKAGE_ENERGY (J)").unwrap(), 100_000.0 * unit);
+    assert_close(column(&header, last, "DRAM_ENERGY (J)").unwrap(), 1_654_321.0 * 15.3e-6);
+
+    // the package counter wrapped around after 4 294 900 000
+    let package = (4_294_967_296.0 - 4_294_900_000.0 + 100_000.0) * unit;
+    assert_close(summary(&stdout).0, package);
+    assert_eq!(domain_energy(&stdout, "PACKAGE_ENERGY (J)"), 10.211);
+    assert_eq!(domain_energy(&stdout, "DRAM_ENERGY (J)"), 10.011);
+    std::fs::remove_file(csv).unwrap();
+}
+
+#[test]
+fn amd_zen3() {
+    let recording = data("zen3.msr.csv");
+    let csv = output("zen3.csv");
+    let stdout = energibridge(&[
+        "--replay-msr",
+        recording.to_str().unwrap(),
+        "--metrics",
+        "CPU_ENERGY*,CORE0_*",
+        "--summary-domains",
+        "--interval",
+        "200",
+        "--output",
+        csv.to_str().unwrap(),
+        "--",
+        "sleep",
+        "0.5",
+    ]);
+
+    // MSR_PWR_UNIT 0xA1003: 2^-16 J
+    let unit = 0.5f64.powi(16);
+    let (header, rows) = read_csv(&csv);
+    let last = rows.last().unwrap();
+    assert_close(column(&header, last, "CPU_ENERGY (J)").unwrap(), 5_655_360.0 * unit);
+    assert_close(column(&header, last, "CORE0_ENERGY (J)").unwrap(), 1_327_680.0 * unit);
+    // FID 0x60, DID 8 and VID 64 of the P-state
+    assert_close(column(&header, last, "CORE0_FREQ (MHZ)").unwrap(), 2400.0);
+    assert_close(column(&header, last, "CORE0_VOLT (V)").unwrap(), 1.15);
+    assert_eq!(column(&header, last, "CORE0_PSTATE"), Some(2.0));
+
+    assert_close(summary(&stdout).0, 10.0);
+    assert_eq!(domain_energy(&stdout, "CORE0_ENERGY (J)"), 5.0);
+    std::fs::remove_file(csv).unwrap();
+}
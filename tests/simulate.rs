@@ -0,0 +1,109 @@
+//! Runs a checked-in scenario with `--simulate` and checks the recorded energy.
+// the energy domains are simulated through the MSR reader, which macOS does not have
+#![cfg(not(target_os = "macos"))]
+
+mod common;
+
+use common::{column, data, domain_energy, energibridge, output, read_csv, summary};
+
+// Range of the package counter: 2^32 units of 2^-14 J.
+const PACKAGE_RANGE: f64 = 262144.0;
+
+#[test]
+fn scenario() {
+    let scenario = data("scenario.toml");
+    let csv = output("scenario.csv");
+    let stdout = energibridge(&[
+        "--simulate",
+        scenario.to_str().unwrap(),
+        "--gpu",
+        "--summary-domains",
+        "--interval",
+        "100",
+        "--output",
+        csv.to_str().unwrap(),
+        "--",
+        "sleep",
+        "1.2",
+    ]);
+    let (header, rows) = read_csv(&csv);
+    std::fs::remove_file(csv).unwrap();
+
+    // the columns stay the same when the DRAM and the GPU fail
+    assert_eq!(
+        header,
+        [
+            "Delta",
+            "Time",
+            "DRAM_ENERGY (J)",
+            "GPU0_POWER (mWatts)",
+            "GPU0_USAGE",
+            "PACKAGE_ENERGY (J)",
+            "USED_MEMORY"
+        ]
+    );
+    assert!(rows.iter().all(|row| row.len() == header.len()));
+    let (first, last) = (rows.first().unwrap(), rows.last().unwrap());
+    for key in ["DRAM_ENERGY (J)", "GPU0_POWER (mWatts)", "GPU0_USAGE"] {
+        assert!(column(&header, first, key).is_some(), "{} is missing", key);
+        assert_eq!(column(&header, last, key), None, "{} did not fail", key);
+    }
+    assert_eq!(column(&header, last, "USED_MEMORY"), Some(4e9));
+
+    // the package counter wraps around once, after 0.5 s
+    let package: Vec<f64> = rows
+        .iter()
+        .map(|row| column(&header, row, "PACKAGE_ENERGY (J)").unwrap())
+        .collect();
+    let wraps = package.windows(2).filter(|pair| pair[1] < pair[0]).count();
+    assert_eq!(wraps, 1);
+    let recorded: f64 = package
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).rem_euclid(PACKAGE_RANGE))
+        .sum();
+
+    // the package draws 20 W and the DRAM 5 W until it fails, between the first sample
+    // and the last one that read them
+    let seconds = |row: &Vec<Option<f64>>| column(&header, row, "Time").unwrap() / 1000.0;
+    let (energy, _) = summary(&stdout);
+    assert!((energy - recorded).abs() < 1e-6, "{} != {}", energy, recorded);
+    let elapsed = seconds(last) - seconds(first);
+    assert!((energy - 20.0 * elapsed).abs() < 1.0, "{} J in {} s", energy, elapsed);
+    assert!((domain_energy(&stdout, "PACKAGE_ENERGY (J)") - energy).abs() < 1e-3);
+
+    let dram = domain_energy(&stdout, "DRAM_ENERGY (J)");
+    let last_read = rows
+        .iter()
+        .rfind(|row| column(&header, row, "DRAM_ENERGY (J)").is_some())
+        .unwrap();
+    let elapsed = seconds(last_read) - seconds(first);
+    assert!((dram - 5.0 * elapsed).abs() < 0.25, "{} J in {} s", dram, elapsed);
+}
+
+#[test]
+fn overhead_columns() {
+    let scenario = data("scenario.toml");
+    let csv = output("overhead.csv");
+    energibridge(&[
+        "--simulate",
+        scenario.to_str().unwrap(),
+        "--overhead",
+        "--interval",
+        "100",
+        "--output",
+        csv.to_str().unwrap(),
+        "--",
+        "sleep",
+        "0.5",
+    ]);
+    let (header, rows) = read_csv(&csv);
+    std::fs::remove_file(csv).unwrap();
+
+    for key in ["ENERGIBRIDGE_CPU_TIME (s)", "ENERGIBRIDGE_ENERGY (J)"] {
+        assert!(
+            rows.iter().all(|row| column(&header, row, key).is_some()),
+            "{} is missing",
+            key
+        );
+    }
+}